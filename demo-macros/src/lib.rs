@@ -0,0 +1,315 @@
+mod tables;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Error, FnArg, Ident, ImplItem, ItemImpl, LitStr, ReturnType, Token, Type};
+
+use tables::{EVENTS, NET_MESSAGES, SVC_MESSAGES, USER_MESSAGES};
+
+// Implements EventHandler, UserMessageEventHandler and GameEventHandler for the type of an
+// inherent impl block from the methods marked with #[on(...)]:
+//
+//     #[demo_handler]
+//     impl Kills {
+//         #[on(game_event = "player_death")]
+//         fn death(&mut self, event: &PlayerDeath) -> Result<Control, Error> { ... }
+//
+//         #[on(user_message = SayText2)]
+//         fn chat(&mut self, message: &CCSUsrMsg_SayText2) { ... }
+//     }
+//
+// Methods take the event the handler method would, optionally followed by the
+// &ParserContext, and return either nothing or Result<Control, Error>. Game event handlers
+// take a &GameEvent or a reference to any type that deserializes from one. The generated
+// EventHandler::wants only accepts the messages handled here, so the parser can skip
+// decoding everything else.
+//
+// The parser only produces raw svc_UserMessage and svc_GameEvent messages. Handlers with
+// user_message or game_event methods have to be wrapped in the decoders that turn those
+// into the events these methods take, or the methods are never called:
+//
+//     parse_dem_file(&mut file, &mut GameEventDecoder::new(UserMessageDecoder(Kills::default())))
+//
+// This is an attribute on the impl block rather than a #[derive(DemoHandler)] on the type
+// because a derive only sees the type definition, not the methods it has to dispatch to.
+#[proc_macro_attribute]
+pub fn demo_handler(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return Error::new(TokenStream2::from(args).into_iter().next().unwrap().span(), "demo_handler takes no arguments").to_compile_error().into();
+    }
+
+    let mut item = parse_macro_input!(input as ItemImpl);
+    match expand(&mut item) {
+        Ok(expanded) => quote!(#item #expanded).into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+enum Target {
+    Event(&'static str),
+    Net(&'static str, &'static str),
+    Svc(&'static str, &'static str),
+    UserMessage(Option<&'static str>, &'static str),
+    GameEvent(LitStr)
+}
+
+struct Handler {
+    ident: Ident,
+    ty: Type,
+    takes_context: bool,
+    returns_control: bool
+}
+
+// Handler methods in the order they were first seen, with every function bound to them.
+#[derive(Default)]
+struct Methods(Vec<(Ident, Type, Vec<TokenStream2>)>);
+
+impl Methods {
+    fn add(&mut self, method: Ident, ty: &Type, call: TokenStream2) {
+        match self.0.iter_mut().find(|(ident, _, _)| *ident == method) {
+            Some((_, _, calls)) => calls.push(call),
+            None => self.0.push((method, ty.clone(), vec![call]))
+        }
+    }
+
+    fn expand(&self) -> TokenStream2 {
+        self.0.iter().map(|(method, ty, calls)| quote! {
+            #[allow(unused_variables)]
+            fn #method(&mut self, event: #ty, context: &::demo::events::ParserContext) -> ::std::result::Result<::demo::events::Control, ::demo::Error> {
+                ::std::result::Result::Ok(::demo::events::Control::Continue #(.max(#calls))*)
+            }
+        }).collect()
+    }
+}
+
+fn lookup(table: &'static [(&'static str, &'static str)], name: &Ident) -> Option<(&'static str, &'static str)> {
+    table.iter().copied().find(|(key, _)| name == key)
+}
+
+fn parse_target(attribute: &syn::Attribute) -> Result<Target, Error> {
+    attribute.parse_args_with(|input: syn::parse::ParseStream| {
+        let key: Ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+
+        if key == "game_event" {
+            return Ok(Target::GameEvent(input.parse()?));
+        }
+
+        let name: Ident = input.parse()?;
+        let unknown = |kind: &str| Error::new(name.span(), format!("Unknown {} `{}`", kind, name));
+        match key.to_string().as_str() {
+            "event" => lookup(EVENTS, &name).map(|(_, method)| Target::Event(method)).ok_or_else(|| unknown("event")),
+            "net" => lookup(NET_MESSAGES, &name).map(|(variant, method)| Target::Net(variant, method)).ok_or_else(|| unknown("net message")),
+            "svc" => lookup(SVC_MESSAGES, &name).map(|(variant, method)| Target::Svc(variant, method)).ok_or_else(|| unknown("svc message")),
+            "user_message" if name == "Unknown" => Ok(Target::UserMessage(None, "on_unknown_user_message")),
+            "user_message" => lookup(USER_MESSAGES, &name).map(|(variant, method)| Target::UserMessage(Some(variant), method)).ok_or_else(|| unknown("user message")),
+            _ => Err(Error::new(key.span(), "Expected one of event, net, svc, user_message or game_event"))
+        }
+    })
+}
+
+fn parse_handler(method: &syn::ImplItemFn) -> Result<Handler, Error> {
+    let mut inputs = method.sig.inputs.iter();
+    let receiver = matches!(inputs.next(), Some(FnArg::Receiver(receiver)) if receiver.mutability.is_some() && receiver.reference.is_some());
+
+    match (receiver, inputs.next(), inputs.next(), inputs.next()) {
+        (true, Some(FnArg::Typed(argument)), context, None) if matches!(context, None | Some(FnArg::Typed(_))) => Ok(Handler {
+            ident: method.sig.ident.clone(),
+            ty: (*argument.ty).clone(),
+            takes_context: context.is_some(),
+            returns_control: !matches!(method.sig.output, ReturnType::Default)
+        }),
+        _ => Err(Error::new_spanned(&method.sig, "Handler methods take &mut self, a reference to the event and optionally the &ParserContext"))
+    }
+}
+
+fn call(handler: &Handler, argument: TokenStream2) -> TokenStream2 {
+    let ident = &handler.ident;
+    let argument = if handler.takes_context { quote!(#argument, context) } else { argument };
+    if handler.returns_control {
+        quote!(self.#ident(#argument)?)
+    } else {
+        quote!({ self.#ident(#argument); ::demo::events::Control::Continue })
+    }
+}
+
+fn game_event_argument(handler: &Handler) -> Result<TokenStream2, Error> {
+    let ty = match &handler.ty {
+        Type::Reference(reference) => &reference.elem,
+        ty => return Err(Error::new_spanned(ty, "Game event handlers take a reference"))
+    };
+
+    match &**ty {
+        Type::Path(path) if path.path.segments.last().is_some_and(|segment| segment.ident == "GameEvent") => Ok(quote!(event)),
+        ty => Ok(quote!(&<#ty as ::demo::events::FromGameEvent>::from_game_event(event)?))
+    }
+}
+
+fn expand(item: &mut ItemImpl) -> Result<TokenStream2, Error> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new_spanned(path, "demo_handler goes on an inherent impl block"));
+    }
+
+    let mut events = Methods::default();
+    let mut user_messages = Methods::default();
+    let mut game_events: Vec<(LitStr, Vec<TokenStream2>)> = Vec::new();
+    let mut wanted: Vec<TokenStream2> = Vec::new();
+
+    let svc = |variant: &str| {
+        let variant = format_ident!("svc_{}", variant);
+        quote!(::demo::events::MessageKind::Svc(::demo::events::SVC_Messages::#variant))
+    };
+
+    for impl_item in &mut item.items {
+        let method = match impl_item {
+            ImplItem::Fn(method) => method,
+            _ => continue
+        };
+
+        let (targets, attributes): (Vec<_>, Vec<_>) = method.attrs.drain(..).partition(|attribute| attribute.path().is_ident("on"));
+        method.attrs = attributes;
+        if targets.is_empty() {
+            continue;
+        }
+
+        let handler = parse_handler(method)?;
+        for target in &targets {
+            match parse_target(target)? {
+                Target::Event(method) => events.add(format_ident!("{}", method), &handler.ty, call(&handler, quote!(event))),
+                Target::Net(variant, method) => {
+                    let variant = format_ident!("net_{}", variant);
+                    wanted.push(quote!(::demo::events::MessageKind::Net(::demo::events::NET_Messages::#variant)));
+                    events.add(format_ident!("{}", method), &handler.ty, call(&handler, quote!(event)));
+                },
+                Target::Svc(variant, method) => {
+                    wanted.push(svc(variant));
+                    events.add(format_ident!("{}", method), &handler.ty, call(&handler, quote!(event)));
+                },
+                Target::UserMessage(variant, method) => {
+                    wanted.push(svc("UserMessage"));
+                    if let Some(variant) = variant {
+                        let variant = format_ident!("CS_UM_{}", variant);
+                        wanted.push(quote!(::demo::events::MessageKind::UserMessage(::demo::events::ECstrike15UserMessages::#variant)));
+                    }
+                    user_messages.add(format_ident!("{}", method), &handler.ty, call(&handler, quote!(event)));
+                },
+                Target::GameEvent(name) => {
+                    wanted.extend(vec![svc("GameEventList"), svc("GameEvent"), quote!(::demo::events::MessageKind::GameEvent(#name))]);
+
+                    let call = call(&handler, game_event_argument(&handler)?);
+                    match game_events.iter_mut().find(|(existing, _)| existing.value() == name.value()) {
+                        Some((_, calls)) => calls.push(call),
+                        None => game_events.push((name, vec![call]))
+                    }
+                }
+            }
+        }
+    }
+
+    let mut patterns: Vec<TokenStream2> = Vec::new();
+    for pattern in wanted {
+        if !patterns.iter().any(|existing| existing.to_string() == pattern.to_string()) {
+            patterns.push(pattern);
+        }
+    }
+    let wants = if patterns.is_empty() {
+        quote!(fn wants(&self, _: &::demo::events::MessageKind<'_>) -> bool { false })
+    } else {
+        quote!(fn wants(&self, message: &::demo::events::MessageKind<'_>) -> bool { matches!(message, #(#patterns)|*) })
+    };
+
+    let decoded_game_event = if game_events.is_empty() {
+        quote!()
+    } else {
+        let (names, calls): (Vec<_>, Vec<_>) = game_events.into_iter().unzip();
+        quote! {
+            #[allow(unused_variables)]
+            fn on_decoded_game_event(&mut self, event: &::demo::events::GameEvent, context: &::demo::events::ParserContext) -> ::std::result::Result<::demo::events::Control, ::demo::Error> {
+                match event.name.as_str() {
+                    #(#names => ::std::result::Result::Ok(::demo::events::Control::Continue #(.max(#calls))*),)*
+                    _ => ::std::result::Result::Ok(::demo::events::Control::Continue)
+                }
+            }
+        }
+    };
+
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    let self_ty = &item.self_ty;
+    let events = events.expand();
+    let user_messages = user_messages.expand();
+
+    Ok(quote! {
+        impl #impl_generics ::demo::events::EventHandler for #self_ty #where_clause {
+            #events
+            #wants
+        }
+
+        impl #impl_generics ::demo::events::UserMessageEventHandler for #self_ty #where_clause {
+            #user_messages
+        }
+
+        impl #impl_generics ::demo::events::GameEventHandler for #self_ty #where_clause {
+            #decoded_game_event
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn expand_error(mut item: ItemImpl) -> String {
+        match expand(&mut item) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string()
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_targets() {
+        assert_eq!(expand_error(parse_quote! {
+            impl Handler {
+                #[on(net = Ticks)]
+                fn tick(&mut self, event: &CNETMsg_Tick) {}
+            }
+        }), "Unknown net message `Ticks`");
+
+        assert_eq!(expand_error(parse_quote! {
+            impl Handler {
+                #[on(event = tick_begin)]
+                fn tick_start(&mut self, event: &TickStart) {}
+            }
+        }), "Unknown event `tick_begin`");
+
+        assert_eq!(expand_error(parse_quote! {
+            impl Handler {
+                #[on(message = Tick)]
+                fn tick(&mut self, event: &CNETMsg_Tick) {}
+            }
+        }), "Expected one of event, net, svc, user_message or game_event");
+    }
+
+    #[test]
+    fn rejects_methods_that_cannot_handle_events() {
+        assert_eq!(expand_error(parse_quote! {
+            impl Handler {
+                #[on(net = Tick)]
+                fn tick(&self, event: &CNETMsg_Tick) {}
+            }
+        }), "Handler methods take &mut self, a reference to the event and optionally the &ParserContext");
+
+        assert_eq!(expand_error(parse_quote! {
+            impl Handler {
+                #[on(game_event = "player_death")]
+                fn death(&mut self, event: PlayerDeath) {}
+            }
+        }), "Game event handlers take a reference");
+
+        assert_eq!(expand_error(parse_quote! {
+            impl EventHandler for Handler {}
+        }), "demo_handler goes on an inherent impl block");
+    }
+}
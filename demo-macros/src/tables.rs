@@ -0,0 +1,179 @@
+// Copied by hand from the handler traits in src/events and kept in the same order. The
+// tests below fail when a trait method is added, removed or renamed without updating these.
+
+// `event = ...`: handler method suffixes that are not decoded from a message.
+pub const EVENTS: &[(&str, &str)] = &[
+    ("dem_header", "on_dem_header"),
+    ("packet_info", "on_packet_info"),
+    ("server_class", "on_server_class"),
+    ("server_class_registry", "on_server_class_registry"),
+    ("string_tables", "on_string_tables"),
+    ("console_cmd", "on_console_cmd"),
+    ("user_cmd", "on_user_cmd"),
+    ("custom_data", "on_custom_data"),
+    ("truncated", "on_truncated"),
+    ("roster_change", "on_roster_change"),
+    ("string_table_entry", "on_string_table_entry"),
+    ("entity_update", "on_entity_update"),
+    ("tick_start", "on_tick_start"),
+    ("tick_end", "on_tick_end"),
+];
+
+// `net = ...`: NET_Messages variant without the net_ prefix.
+pub const NET_MESSAGES: &[(&str, &str)] = &[
+    ("NOP", "on_nop"),
+    ("Disconnect", "on_disconnect"),
+    ("File", "on_file"),
+    ("SplitScreenUser", "on_split_screen_user"),
+    ("Tick", "on_tick"),
+    ("StringCmd", "on_string_cmd"),
+    ("SetConVar", "on_set_con_var"),
+    ("SignonState", "on_signon_state"),
+    ("PlayerAvatarData", "on_player_avatar_data"),
+];
+
+// `svc = ...`: SVC_Messages variant without the svc_ prefix.
+pub const SVC_MESSAGES: &[(&str, &str)] = &[
+    ("ServerInfo", "on_server_info"),
+    ("SendTable", "on_send_table"),
+    ("ClassInfo", "on_class_info"),
+    ("SetPause", "on_set_pause"),
+    ("CreateStringTable", "on_create_string_table"),
+    ("UpdateStringTable", "on_update_string_table"),
+    ("VoiceInit", "on_voice_init"),
+    ("VoiceData", "on_voice_data"),
+    ("Print", "on_print"),
+    ("Sounds", "on_sounds"),
+    ("SetView", "on_set_view"),
+    ("FixAngle", "on_fix_angle"),
+    ("CrosshairAngle", "on_crosshair_angle"),
+    ("BSPDecal", "on_bspdecal"),
+    ("SplitScreen", "on_split_screen"),
+    ("UserMessage", "on_user_message"),
+    ("EntityMessage", "on_entity_message"),
+    ("GameEvent", "on_game_event"),
+    ("PacketEntities", "on_packet_entities"),
+    ("TempEntities", "on_temp_entities"),
+    ("Prefetch", "on_prefetch"),
+    ("Menu", "on_menu"),
+    ("GameEventList", "on_game_event_list"),
+    ("GetCvarValue", "on_get_cvar_value"),
+    ("PaintmapData", "on_paintmap_data"),
+    ("CmdKeyValues", "on_cmd_key_values"),
+    ("EncryptedData", "on_encrypted_data"),
+    ("HltvReplay", "on_hltv_replay"),
+    ("Broadcast_Command", "on_broadcast_command"),
+];
+
+// `user_message = ...`: ECstrike15UserMessages variant without the CS_UM_ prefix.
+pub const USER_MESSAGES: &[(&str, &str)] = &[
+    ("VGUIMenu", "on_vguimenu"),
+    ("Geiger", "on_geiger"),
+    ("Train", "on_train"),
+    ("HudText", "on_hud_text"),
+    ("SayText", "on_say_text"),
+    ("SayText2", "on_say_text2"),
+    ("TextMsg", "on_text_msg"),
+    ("HudMsg", "on_hud_msg"),
+    ("ResetHud", "on_reset_hud"),
+    ("GameTitle", "on_game_title"),
+    ("Shake", "on_shake"),
+    ("Fade", "on_fade"),
+    ("Rumble", "on_rumble"),
+    ("CloseCaption", "on_close_caption"),
+    ("CloseCaptionDirect", "on_close_caption_direct"),
+    ("SendAudio", "on_send_audio"),
+    ("RawAudio", "on_raw_audio"),
+    ("VoiceMask", "on_voice_mask"),
+    ("RequestState", "on_request_state"),
+    ("Damage", "on_damage"),
+    ("RadioText", "on_radio_text"),
+    ("HintText", "on_hint_text"),
+    ("KeyHintText", "on_key_hint_text"),
+    ("ProcessSpottedEntityUpdate", "on_process_spotted_entity_update"),
+    ("ReloadEffect", "on_reload_effect"),
+    ("AdjustMoney", "on_adjust_money"),
+    ("UpdateTeamMoney", "on_update_team_money"),
+    ("StopSpectatorMode", "on_stop_spectator_mode"),
+    ("KillCam", "on_kill_cam"),
+    ("DesiredTimescale", "on_desired_timescale"),
+    ("CurrentTimescale", "on_current_timescale"),
+    ("AchievementEvent", "on_achievement_event"),
+    ("MatchEndConditions", "on_match_end_conditions"),
+    ("DisconnectToLobby", "on_disconnect_to_lobby"),
+    ("PlayerStatsUpdate", "on_player_stats_update"),
+    ("DisplayInventory", "on_display_inventory"),
+    ("WarmupHasEnded", "on_warmup_has_ended"),
+    ("ClientInfo", "on_client_info"),
+    ("XRankGet", "on_xrank_get"),
+    ("XRankUpd", "on_xrank_upd"),
+    ("CallVoteFailed", "on_call_vote_failed"),
+    ("VoteStart", "on_vote_start"),
+    ("VotePass", "on_vote_pass"),
+    ("VoteFailed", "on_vote_failed"),
+    ("VoteSetup", "on_vote_setup"),
+    ("ServerRankRevealAll", "on_server_rank_reveal_all"),
+    ("SendLastKillerDamageToClient", "on_send_last_killer_damage_to_client"),
+    ("ServerRankUpdate", "on_server_rank_update"),
+    ("ItemPickup", "on_item_pickup"),
+    ("ShowMenu", "on_show_menu"),
+    ("BarTime", "on_bar_time"),
+    ("AmmoDenied", "on_ammo_denied"),
+    ("MarkAchievement", "on_mark_achievement"),
+    ("MatchStatsUpdate", "on_match_stats_update"),
+    ("ItemDrop", "on_item_drop"),
+    ("GlowPropTurnOff", "on_glow_prop_turn_off"),
+    ("SendPlayerItemDrops", "on_send_player_item_drops"),
+    ("RoundBackupFilenames", "on_round_backup_filenames"),
+    ("SendPlayerItemFound", "on_send_player_item_found"),
+    ("ReportHit", "on_report_hit"),
+    ("XpUpdate", "on_xp_update"),
+    ("QuestProgress", "on_quest_progress"),
+    ("ScoreLeaderboardData", "on_score_leaderboard_data"),
+    ("PlayerDecalDigitalSignature", "on_player_decal_digital_signature"),
+    ("WeaponSound", "on_weapon_sound"),
+    ("UpdateScreenHealthBar", "on_update_screen_health_bar"),
+    ("EntityOutlineHighlight", "on_entity_outline_highlight"),
+    ("SSUI", "on_ssui"),
+    ("SurvivalStats", "on_survival_stats"),
+    ("DisconnectToLobby2", "on_disconnect_to_lobby2"),
+    ("EndOfMatchAllPlayersData", "on_end_of_match_all_players_data"),
+    ("RoundImpactScoreData", "on_round_impact_score_data"),
+    ("CurrentRoundOdds", "on_current_round_odds"),
+    ("DeepStats", "on_deep_stats"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENTS_SOURCE: &str = include_str!("../../src/events/mod.rs");
+
+    // The method names of the trait's on_fn! list, split at its blank lines.
+    fn trait_methods(name: &str) -> Vec<Vec<&'static str>> {
+        let start = EVENTS_SOURCE.find(&format!("pub trait {}", name)).unwrap();
+        let list = &EVENTS_SOURCE[start..];
+        let list = &list[list.find("on_fn! {").unwrap() + "on_fn! {".len()..];
+        let list = &list[..list.find("\n    }").unwrap()];
+
+        list.split("\n\n")
+            .map(|group| group.lines().filter_map(|line| line.trim().split(" =>").next().filter(|method| method.starts_with("on_"))).collect())
+            .collect()
+    }
+
+    fn methods(table: &[(&str, &'static str)]) -> Vec<&'static str> {
+        table.iter().map(|(_, method)| *method).collect()
+    }
+
+    #[test]
+    fn event_tables_match_event_handler() {
+        assert_eq!(trait_methods("EventHandler"), vec![methods(EVENTS), methods(NET_MESSAGES), methods(SVC_MESSAGES)]);
+    }
+
+    #[test]
+    fn user_message_table_matches_user_message_event_handler() {
+        let mut expected = methods(USER_MESSAGES);
+        expected.push("on_unknown_user_message");
+        assert_eq!(trait_methods("UserMessageEventHandler").concat(), expected);
+    }
+}
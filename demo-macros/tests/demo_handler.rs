@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use demo::{demo_handler, events::*, Error};
+
+#[derive(Default)]
+struct Recorder {
+    calls: Vec<String>
+}
+
+#[demo_handler]
+impl Recorder {
+    #[on(event = tick_start)]
+    fn tick_start(&mut self, event: &TickStart, context: &ParserContext) {
+        self.calls.push(format!("tick_start {} in frame {}", event.tick, context.frame));
+    }
+
+    #[on(net = Tick)]
+    fn tick(&mut self, _: &CNETMsg_Tick) -> Result<Control, Error> {
+        self.calls.push("tick".to_owned());
+        Ok(Control::SkipPacket)
+    }
+
+    #[on(svc = ServerInfo)]
+    fn server_info(&mut self, _: &CSVCMsg_ServerInfo) {
+        self.calls.push("server_info".to_owned());
+    }
+
+    #[on(user_message = SayText2)]
+    fn say_text2(&mut self, _: &CCSUsrMsg_SayText2) {
+        self.calls.push("say_text2".to_owned());
+    }
+
+    #[on(user_message = Unknown)]
+    fn unknown_user_message(&mut self, event: &UnknownUserMessage<'_>) {
+        self.calls.push(format!("unknown_user_message {}", event.msg_type));
+    }
+
+    #[on(game_event = "player_death")]
+    fn player_death(&mut self, event: &PlayerDeath, context: &ParserContext) -> Result<Control, Error> {
+        self.calls.push(format!("player_death {} at tick {}", event.user_id, context.tick));
+        Ok(Control::Stop)
+    }
+
+    #[on(game_event = "player_death")]
+    #[on(game_event = "round_start")]
+    fn game_event(&mut self, event: &GameEvent) {
+        self.calls.push(event.name.clone());
+    }
+}
+
+struct Nothing;
+
+#[demo_handler]
+impl Nothing {}
+
+fn game_event(name: &str, fields: Vec<(&str, GameEventValue)>) -> GameEvent {
+    GameEvent {
+        event_id: 0,
+        name: name.to_owned(),
+        fields: fields.into_iter().map(|(key, value)| (key.to_owned(), value)).collect::<HashMap<_, _>>()
+    }
+}
+
+#[test]
+fn dispatches_each_target_kind() {
+    let mut handler = Recorder::default();
+    let context = ParserContext { tick: 7, frame: 2, ..ParserContext::default() };
+
+    handler.on_tick_start(&TickStart { tick: 7 }, &context).unwrap();
+    handler.on_tick(&CNETMsg_Tick::default(), &context).unwrap();
+    handler.on_server_info(&CSVCMsg_ServerInfo::default(), &context).unwrap();
+    handler.on_say_text2(&CCSUsrMsg_SayText2::default(), &context).unwrap();
+    handler.on_unknown_user_message(&UnknownUserMessage { msg_type: 999, data: &[] }, &context).unwrap();
+    handler.on_decoded_game_event(&game_event("player_death", vec![("userid", GameEventValue::Short(3))]), &context).unwrap();
+    handler.on_decoded_game_event(&game_event("round_start", vec![]), &context).unwrap();
+    handler.on_decoded_game_event(&game_event("player_hurt", vec![]), &context).unwrap();
+
+    assert_eq!(handler.calls, vec![
+        "tick_start 7 in frame 2",
+        "tick",
+        "server_info",
+        "say_text2",
+        "unknown_user_message 999",
+        "player_death 3 at tick 7",
+        "player_death",
+        "round_start"
+    ]);
+}
+
+#[test]
+fn returns_the_control_of_result_handlers() {
+    let mut handler = Recorder::default();
+    let context = ParserContext::default();
+
+    assert_eq!(handler.on_tick(&CNETMsg_Tick::default(), &context).unwrap(), Control::SkipPacket);
+    assert_eq!(handler.on_server_info(&CSVCMsg_ServerInfo::default(), &context).unwrap(), Control::Continue);
+    // The most severe control of every method bound to the event.
+    assert_eq!(handler.on_decoded_game_event(&game_event("player_death", vec![]), &context).unwrap(), Control::Stop);
+    assert_eq!(handler.on_decoded_game_event(&game_event("round_start", vec![]), &context).unwrap(), Control::Continue);
+}
+
+#[test]
+fn wants_only_the_declared_targets() {
+    let handler = Recorder::default();
+    for message in &[
+        MessageKind::Net(NET_Messages::net_Tick),
+        MessageKind::Svc(SVC_Messages::svc_ServerInfo),
+        MessageKind::Svc(SVC_Messages::svc_UserMessage),
+        MessageKind::UserMessage(ECstrike15UserMessages::CS_UM_SayText2),
+        MessageKind::Svc(SVC_Messages::svc_GameEventList),
+        MessageKind::Svc(SVC_Messages::svc_GameEvent),
+        MessageKind::GameEvent("player_death"),
+        MessageKind::GameEvent("round_start")
+    ] {
+        assert!(handler.wants(message), "{:?}", message);
+    }
+
+    for message in &[
+        MessageKind::Net(NET_Messages::net_NOP),
+        MessageKind::Svc(SVC_Messages::svc_PacketEntities),
+        MessageKind::UserMessage(ECstrike15UserMessages::CS_UM_TextMsg),
+        MessageKind::GameEvent("player_hurt")
+    ] {
+        assert!(!handler.wants(message), "{:?}", message);
+    }
+
+    assert!(!Nothing.wants(&MessageKind::Net(NET_Messages::net_Tick)));
+}
@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use demo::{DemoParser, ParseOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let options = ParseOptions {
+        max_frame_size: 1024 * 1024,
+        lenient: true,
+        decode_string_tables: true,
+        decode_entities: true
+    };
+
+    // The iterator ends after the first error.
+    for _ in DemoParser::with_options(data, options) {}
+});
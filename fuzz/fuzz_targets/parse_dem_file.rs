@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use demo::{events::*, parse_dem_file_with_options, ParseOptions};
+
+struct NoOpHandler;
+
+impl EventHandler for NoOpHandler {}
+impl UserMessageEventHandler for NoOpHandler {}
+
+fuzz_target!(|data: &[u8]| {
+    let options = ParseOptions {
+        max_frame_size: 1024 * 1024,
+        decode_string_tables: true,
+        decode_entities: true,
+        ..ParseOptions::default()
+    };
+
+    let _ = parse_dem_file_with_options(&mut &data[..], &mut UserMessageDecoder(NoOpHandler), &options);
+});
@@ -0,0 +1,28 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use std::io::Read;
+
+use demo::{events::*, parse_dem_file_with_options, ParseOptions};
+
+struct NoOpHandler;
+
+impl EventHandler for NoOpHandler {}
+impl UserMessageEventHandler for NoOpHandler {}
+
+// Prepend a blank header so the fuzzer spends its time on the frame parsers.
+const HEADER_SIZE: usize = std::mem::size_of::<DemHeader>();
+
+fuzz_target!(|data: &[u8]| {
+    let options = ParseOptions {
+        max_frame_size: 1024 * 1024,
+        decode_string_tables: true,
+        decode_entities: true,
+        ..ParseOptions::default()
+    };
+
+    let header = [0u8; HEADER_SIZE];
+    let mut reader = (&header[..]).chain(data);
+
+    let _ = parse_dem_file_with_options(&mut reader, &mut UserMessageDecoder(NoOpHandler), &options);
+});
@@ -0,0 +1,194 @@
+use crate::{DemoError, Error};
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    // Source's bf_read packs values LSB first.
+    pub fn read_bits(&mut self, count: u32) -> Result<u32, Error> {
+        if count > 32 {
+            return Err(DemoError::invalid_data(format!("Cannot read {} bits at once", count)));
+        }
+        if self.remaining() < count as usize {
+            return Err(DemoError::truncated());
+        }
+
+        let mut value = 0u64;
+        let mut read = 0;
+        while read < count {
+            let byte = self.data[self.position / 8] as u64;
+            let offset = (self.position % 8) as u32;
+            let take = (8 - offset).min(count - read);
+
+            value |= ((byte >> offset) & ((1 << take) - 1)) << read;
+            read += take;
+            self.position += take as usize;
+        }
+
+        Ok(value as u32)
+    }
+
+    pub fn read_signed_bits(&mut self, count: u32) -> Result<i32, Error> {
+        let value = self.read_bits(count)?;
+        if count == 0 || count == 32 {
+            return Ok(value as i32);
+        }
+
+        let shift = 32 - count;
+        Ok(((value << shift) as i32) >> shift)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bits(8)? as u8)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(self.read_bits(16)? as u16)
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, Error> {
+        Ok(self.read_bits(16)? as u16 as i16)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        self.read_bits(32)
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_bits(self.read_bits(32)?))
+    }
+
+    pub fn read_var_u32(&mut self) -> Result<u32, Error> {
+        let mut value = 0;
+        for shift_amount in 0..5 {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7F) as u32) << (shift_amount * 7);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DemoError::invalid_varint())
+    }
+
+    pub fn read_var_u64(&mut self) -> Result<u64, Error> {
+        let mut value = 0;
+        for shift_amount in 0..10 {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7F) as u64) << (shift_amount * 7);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DemoError::invalid_varint())
+    }
+
+    pub fn read_signed_var_i32(&mut self) -> Result<i32, Error> {
+        let value = self.read_var_u32()?;
+        Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+    }
+
+    pub fn read_signed_var_i64(&mut self) -> Result<i64, Error> {
+        let value = self.read_var_u64()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    // CBitRead::ReadUBitVar
+    pub fn read_ubitvar(&mut self) -> Result<u32, Error> {
+        let value = self.read_bits(6)?;
+        match value & (16 | 32) {
+            16 => Ok((value & 15) | (self.read_bits(4)? << 4)),
+            32 => Ok((value & 15) | (self.read_bits(8)? << 4)),
+            48 => Ok((value & 15) | (self.read_bits(32 - 4)? << 4)),
+            _ => Ok(value)
+        }
+    }
+
+    pub fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>, Error> {
+        if self.remaining() / 8 < count {
+            return Err(DemoError::truncated());
+        }
+
+        let mut buffer = Vec::with_capacity(count);
+        for _ in 0..count {
+            buffer.push(self.read_u8()?);
+        }
+        Ok(buffer)
+    }
+
+    // Reads a bit count that need not be byte aligned, padding the last byte with zeros.
+    pub fn read_bits_to_bytes(&mut self, count: usize) -> Result<Vec<u8>, Error> {
+        if self.remaining() < count {
+            return Err(DemoError::truncated());
+        }
+
+        let mut buffer = self.read_bytes(count / 8)?;
+        let tail_bits = (count % 8) as u32;
+        if tail_bits > 0 {
+            buffer.push(self.read_bits(tail_bits)? as u8);
+        }
+        Ok(buffer)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, Error> {
+        let mut string_buffer = Vec::with_capacity(256);
+        let mut byte = self.read_u8()?;
+        while byte != 0 {
+            string_buffer.push(byte);
+            byte = self.read_u8()?;
+        }
+        Ok(String::from_utf8(string_buffer)?)
+    }
+}
+
+// Writes bits in the order BitReader reads them, used to build test input.
+#[cfg(test)]
+#[derive(Default)]
+pub struct BitWriter {
+    pub data: Vec<u8>,
+    position: usize
+}
+
+#[cfg(test)]
+impl BitWriter {
+    pub fn bits(mut self, value: u32, count: u32) -> Self {
+        for bit in 0..count {
+            if self.position == self.data.len() * 8 {
+                self.data.push(0);
+            }
+            if value >> bit & 1 == 1 {
+                self.data[self.position / 8] |= 1 << (self.position % 8);
+            }
+            self.position += 1;
+        }
+        self
+    }
+
+    pub fn bit(self, value: bool) -> Self {
+        self.bits(value as u32, 1)
+    }
+
+    pub fn bytes(self, bytes: &[u8]) -> Self {
+        bytes.iter().fold(self, |writer, &byte| writer.bits(byte as u32, 8))
+    }
+
+    pub fn float(self, value: f32) -> Self {
+        self.bits(value.to_bits(), 32)
+    }
+}
@@ -0,0 +1,155 @@
+use std::borrow::Cow;
+use std::convert::TryInto;
+
+use crate::{DemoError, Error};
+
+const LZSS_MAGIC: &[u8; 4] = b"LZSS";
+const SNAPPY_MAGIC: &[u8; 4] = b"SNAP";
+
+// Mirrors COM_BufferToBufferDecompress: payloads are only treated as compressed
+// when they start with one of the known magics and are returned unchanged otherwise.
+pub fn decompress(data: &[u8], limit: u32) -> Result<Cow<'_, [u8]>, Error> {
+    if data.starts_with(LZSS_MAGIC) {
+        Ok(Cow::Owned(decompress_lzss(&data[4..], limit)?))
+    } else if data.starts_with(SNAPPY_MAGIC) {
+        Ok(Cow::Owned(decompress_snappy(&data[4..], limit)?))
+    } else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+fn check_size(size: u64, limit: u32) -> Result<usize, Error> {
+    if size > limit as u64 {
+        return Err(DemoError::limit_exceeded(size, limit as u64));
+    }
+    Ok(size as usize)
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, Error> {
+    let bytes = data.get(offset..offset + 4).ok_or_else(DemoError::truncated)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn next_byte(data: &[u8], position: &mut usize) -> Result<u8, Error> {
+    let byte = *data.get(*position).ok_or_else(DemoError::truncated)?;
+    *position += 1;
+    Ok(byte)
+}
+
+fn copy_back_reference(output: &mut Vec<u8>, offset: usize, length: usize) -> Result<(), Error> {
+    if offset == 0 || offset > output.len() {
+        return Err(DemoError::invalid_data(format!("Back reference {} outside of {} decompressed bytes", offset, output.len())));
+    }
+
+    // Overlapping copies repeat the bytes that were just written.
+    let start = output.len() - offset;
+    for index in start..start + length {
+        output.push(output[index]);
+    }
+    Ok(())
+}
+
+// CLZSS::Uncompress: a command byte announces whether each of the next eight
+// items is a literal or a 12 bit position / 4 bit length back reference.
+fn decompress_lzss(data: &[u8], limit: u32) -> Result<Vec<u8>, Error> {
+    let actual_size = check_size(read_u32_le(data, 0)? as u64, limit)?;
+    let mut output = Vec::with_capacity(actual_size);
+    let mut position = 4;
+
+    let mut command = 0;
+    let mut command_bits = 0;
+    loop {
+        if command_bits == 0 {
+            command = next_byte(data, &mut position)?;
+            command_bits = 8;
+        }
+        command_bits -= 1;
+
+        if command & 1 == 1 {
+            let high = next_byte(data, &mut position)? as usize;
+            let low = next_byte(data, &mut position)? as usize;
+
+            let offset = (high << 4 | low >> 4) + 1;
+            let length = (low & 0x0F) + 1;
+            if length == 1 {
+                break;
+            }
+            copy_back_reference(&mut output, offset, length)?;
+        } else {
+            output.push(next_byte(data, &mut position)?);
+        }
+
+        if output.len() > actual_size {
+            break;
+        }
+        command >>= 1;
+    }
+
+    if output.len() != actual_size {
+        return Err(DemoError::size_mismatch(actual_size as u64, output.len() as u64));
+    }
+    Ok(output)
+}
+
+fn decompress_snappy(data: &[u8], limit: u32) -> Result<Vec<u8>, Error> {
+    let mut position = 0;
+
+    let mut uncompressed_size = 0u64;
+    for shift in (0..35).step_by(7) {
+        let byte = next_byte(data, &mut position)?;
+        uncompressed_size |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let uncompressed_size = check_size(uncompressed_size, limit)?;
+
+    let mut output = Vec::with_capacity(uncompressed_size);
+    while position < data.len() {
+        let tag = next_byte(data, &mut position)?;
+
+        let (offset, length) = match tag & 0x03 {
+            // Literal, lengths above 60 are stored in the following 1-4 bytes.
+            0 => {
+                let mut length = (tag >> 2) as usize;
+                if length >= 60 {
+                    let bytes = length - 59;
+                    length = 0;
+                    for shift in 0..bytes {
+                        length |= (next_byte(data, &mut position)? as usize) << (shift * 8);
+                    }
+                }
+                let length = length + 1;
+
+                let literal = data.get(position..position + length).ok_or_else(DemoError::truncated)?;
+                output.extend_from_slice(literal);
+                position += length;
+                continue;
+            },
+            1 => {
+                let length = 4 + ((tag >> 2) & 0x07) as usize;
+                let offset = ((tag as usize >> 5) << 8) | next_byte(data, &mut position)? as usize;
+                (offset, length)
+            },
+            2 => {
+                let offset = next_byte(data, &mut position)? as usize | (next_byte(data, &mut position)? as usize) << 8;
+                (offset, 1 + (tag >> 2) as usize)
+            },
+            _ => {
+                let offset = read_u32_le(data, position)? as usize;
+                position += 4;
+                (offset, 1 + (tag >> 2) as usize)
+            }
+        };
+
+        copy_back_reference(&mut output, offset, length)?;
+        if output.len() > uncompressed_size {
+            break;
+        }
+    }
+
+    if output.len() != uncompressed_size {
+        return Err(DemoError::size_mismatch(uncompressed_size as u64, output.len() as u64));
+    }
+    Ok(output)
+}
@@ -0,0 +1,544 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::bitreader::BitReader;
+use crate::players::PlayerRoster;
+use crate::props::decode_prop;
+use crate::protos::netmessages::CSVCMsg_PacketEntities;
+use crate::sendtables::{FlattenedClass, FlattenedProp, ServerClassRegistry};
+use crate::{DemoError, Error};
+
+pub use crate::props::PropValue;
+
+pub const MAX_EDICT_BITS: u32 = 11;
+pub const MAX_EDICTS: usize = 1 << MAX_EDICT_BITS;
+pub const NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS: u32 = 10;
+
+// Field index deltas of this value terminate a prop list.
+const FIELD_INDEX_END: u32 = 0xFFF;
+
+// A networked EHANDLE: the entity index in the low MAX_EDICT_BITS, the serial number above it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntityHandle(pub u32);
+
+impl EntityHandle {
+    // INVALID_NETWORKED_EHANDLE_VALUE, sent for handles that point at nothing.
+    pub const INVALID: EntityHandle = EntityHandle((1 << (MAX_EDICT_BITS + NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS)) - 1);
+
+    pub fn new(index: u32, serial: u32) -> Self {
+        let serial = serial & ((1 << NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS) - 1);
+        EntityHandle((serial << MAX_EDICT_BITS) | (index & (MAX_EDICTS as u32 - 1)))
+    }
+
+    // Handle props are decoded as plain ints.
+    pub fn from_prop(value: &PropValue) -> Option<Self> {
+        value.as_int().map(|value| EntityHandle(value as u32)).filter(EntityHandle::is_valid)
+    }
+
+    pub fn index(&self) -> u32 {
+        self.0 & (MAX_EDICTS as u32 - 1)
+    }
+
+    pub fn serial(&self) -> u32 {
+        (self.0 >> MAX_EDICT_BITS) & ((1 << NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS) - 1)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        *self != EntityHandle::INVALID
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entity {
+    pub index: u32,
+    pub serial: u32,
+    pub class: Arc<FlattenedClass>,
+    // Indexed like `class.props`, empty until the server sent a value.
+    values: Vec<Option<PropValue>>
+}
+
+impl Entity {
+    fn new(index: u32, serial: u32, class: Arc<FlattenedClass>) -> Self {
+        Entity {
+            index,
+            serial,
+            values: vec![None; class.props.len()],
+            class
+        }
+    }
+
+    fn with_baseline(index: u32, serial: u32, class: Arc<FlattenedClass>, baseline: Option<&[Option<PropValue>]>) -> Self {
+        match baseline {
+            Some(values) => Entity { index, serial, class, values: values.to_vec() },
+            None => Entity::new(index, serial, class)
+        }
+    }
+
+    pub fn handle(&self) -> EntityHandle {
+        EntityHandle::new(self.index, self.serial)
+    }
+
+    pub fn class_id(&self) -> u16 {
+        self.class.id
+    }
+
+    pub fn class_name(&self) -> &str {
+        &self.class.name
+    }
+
+    pub fn prop(&self, name: &str) -> Option<&PropValue> {
+        self.class.prop_index(name).and_then(|index| self.prop_at(index))
+    }
+
+    pub fn prop_at(&self, index: usize) -> Option<&PropValue> {
+        self.values.get(index).and_then(Option::as_ref)
+    }
+
+    pub fn props(&self) -> impl Iterator<Item = (&FlattenedProp, &PropValue)> {
+        self.class.props.iter()
+            .zip(&self.values)
+            .filter_map(|(prop, value)| value.as_ref().map(|value| (prop, value)))
+    }
+
+    // Mirrors ReadFieldList: all changed indices come first, followed by their values.
+    fn read_props(&mut self, reader: &mut BitReader, changed: &mut Vec<usize>) -> Result<(), Error> {
+        let new_way = reader.read_bit()?;
+
+        let mut index = -1;
+        while let Some(next) = read_field_index(reader, index, new_way)? {
+            changed.push(next as usize);
+            index = next;
+        }
+
+        for &index in changed.iter() {
+            let prop = self.class.props.get(index).ok_or_else(|| {
+                DemoError::invalid_data(format!("Prop index {} out of range for {}", index, self.class.name))
+            })?;
+            self.values[index] = Some(decode_prop(reader, prop)?);
+        }
+
+        Ok(())
+    }
+}
+
+fn read_field_index(reader: &mut BitReader, last_index: i64, new_way: bool) -> Result<Option<i64>, Error> {
+    if new_way && reader.read_bit()? {
+        return Ok(Some(last_index + 1));
+    }
+
+    let delta = if new_way && reader.read_bit()? {
+        reader.read_bits(3)?
+    } else {
+        let value = reader.read_bits(7)?;
+        match value & (32 | 64) {
+            32 => (value & !96) | (reader.read_bits(2)? << 5),
+            64 => (value & !96) | (reader.read_bits(4)? << 5),
+            96 => (value & !96) | (reader.read_bits(7)? << 5),
+            _ => value
+        }
+    };
+
+    if delta == FIELD_INDEX_END {
+        return Ok(None);
+    }
+    Ok(Some(last_index + 1 + delta as i64))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EntityUpdateKind {
+    Created,
+    Updated,
+    LeftPvs,
+    Deleted
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct EntityUpdate<'a> {
+    pub kind: EntityUpdateKind,
+    pub entity: &'a Entity,
+    // Indices into `entity.class.props` that were sent with this update.
+    pub changed_props: &'a [usize]
+}
+
+// Raw instancebaseline entries keyed by class id. They are only decoded once an
+// entity of that class is created, since the string table can arrive before the
+// data tables needed to decode it.
+#[derive(Clone, Debug, Default)]
+struct Baselines {
+    raw: HashMap<u16, Vec<u8>>,
+    decoded: HashMap<u16, Vec<Option<PropValue>>>
+}
+
+impl Baselines {
+    fn set(&mut self, class_id: u16, data: Vec<u8>) {
+        self.decoded.remove(&class_id);
+        self.raw.insert(class_id, data);
+    }
+
+    fn get(&mut self, class: &Arc<FlattenedClass>) -> Result<Option<&[Option<PropValue>]>, Error> {
+        if !self.decoded.contains_key(&class.id) {
+            let data = match self.raw.get(&class.id) {
+                Some(data) => data,
+                None => return Ok(None)
+            };
+
+            let mut baseline = Entity::new(0, 0, Arc::clone(class));
+            baseline.read_props(&mut BitReader::new(data), &mut Vec::new())?;
+            self.decoded.insert(class.id, baseline.values);
+        }
+
+        Ok(self.decoded.get(&class.id).map(|values| &values[..]))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EntityTable {
+    entities: Vec<Option<Entity>>,
+    baselines: Baselines
+}
+
+impl Default for EntityTable {
+    fn default() -> Self {
+        EntityTable {
+            entities: vec![None; MAX_EDICTS],
+            baselines: Baselines::default()
+        }
+    }
+}
+
+impl EntityTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, index: u32) -> Option<&Entity> {
+        self.entities.get(index as usize).and_then(Option::as_ref)
+    }
+
+    // Slots are reused once an entity is deleted, so handles only resolve while the serial
+    // number still matches the entity occupying their slot.
+    pub fn get_by_handle(&self, handle: EntityHandle) -> Option<&Entity> {
+        if !handle.is_valid() {
+            return None;
+        }
+        self.get(handle.index()).filter(|entity| entity.serial == handle.serial())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter().filter_map(Option::as_ref)
+    }
+
+    pub fn iter_by_class<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Entity> {
+        self.iter().filter(move |entity| entity.class_name() == name)
+    }
+
+    // User ids are only known to the userinfo string table, hence the roster.
+    pub fn find_player_by_user_id(&self, players: &PlayerRoster, user_id: i32) -> Option<&Entity> {
+        players.by_user_id(user_id).and_then(|player| self.get(player.entity_index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.iter_mut().for_each(|entity| *entity = None);
+        self.baselines.decoded.clear();
+    }
+
+    // Stores an entry of the instancebaseline string table, keyed by class id.
+    pub(crate) fn set_baseline(&mut self, key: &str, data: Vec<u8>) {
+        if let Ok(class_id) = key.parse() {
+            self.baselines.set(class_id, data);
+        }
+    }
+
+    pub(crate) fn read_packet_entities<F>(&mut self, registry: &ServerClassRegistry, message: &CSVCMsg_PacketEntities, mut on_update: F) -> Result<(), Error>
+    where
+        F: FnMut(&EntityUpdate) -> Result<(), Error>
+    {
+        let reader = &mut BitReader::new(message.get_entity_data());
+        let class_id_bits = registry.class_id_bits();
+
+        let mut changed = Vec::new();
+        let mut index = -1i64;
+
+        // A full update lists every entity the client keeps, all others are deleted.
+        let mut listed = if message.get_is_delta() { Vec::new() } else { vec![false; MAX_EDICTS] };
+
+        for _ in 0..message.get_updated_entries() {
+            index += 1 + reader.read_ubitvar()? as i64;
+            if index as usize >= MAX_EDICTS {
+                return Err(DemoError::invalid_data(format!("Entity index {} out of range", index)));
+            }
+            let slot = &mut self.entities[index as usize];
+            let baselines = &mut self.baselines;
+            if let Some(listed) = listed.get_mut(index as usize) {
+                *listed = true;
+            }
+
+            changed.clear();
+
+            if reader.read_bit()? {
+                // Leaving the PVS keeps the entity around unless it was deleted as well.
+                let delete = reader.read_bit()?;
+
+                if delete {
+                    if let Some(entity) = slot.take() {
+                        on_update(&EntityUpdate { kind: EntityUpdateKind::Deleted, entity: &entity, changed_props: &changed })?;
+                    }
+                } else if let Some(entity) = slot {
+                    on_update(&EntityUpdate { kind: EntityUpdateKind::LeftPvs, entity, changed_props: &changed })?;
+                }
+            } else if reader.read_bit()? {
+                let class_id = reader.read_bits(class_id_bits)? as u16;
+                let serial = reader.read_bits(NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS)?;
+
+                let class = registry.class(class_id).ok_or_else(|| {
+                    DemoError::invalid_data(format!("Unknown server class {}", class_id))
+                })?;
+
+                // A different entity taking over the slot implies the old one is gone.
+                if let Some(previous) = slot.take() {
+                    if previous.serial != serial || previous.class_id() != class_id {
+                        on_update(&EntityUpdate { kind: EntityUpdateKind::Deleted, entity: &previous, changed_props: &changed })?;
+                    }
+                }
+
+                let baseline = baselines.get(class)?;
+                let mut entity = Entity::with_baseline(index as u32, serial, Arc::clone(class), baseline);
+                entity.read_props(reader, &mut changed)?;
+
+                let entity = slot.insert(entity);
+                on_update(&EntityUpdate { kind: EntityUpdateKind::Created, entity, changed_props: &changed })?;
+            } else {
+                let entity = slot.as_mut().ok_or_else(|| {
+                    DemoError::invalid_data(format!("Delta update for missing entity {}", index))
+                })?;
+                entity.read_props(reader, &mut changed)?;
+
+                on_update(&EntityUpdate { kind: EntityUpdateKind::Updated, entity, changed_props: &changed })?;
+            }
+        }
+
+        // Mirrors the engine's explicit delete pass after a full update.
+        changed.clear();
+        for (slot, _) in self.entities.iter_mut().zip(&listed).filter(|(_, &listed)| !listed) {
+            if let Some(entity) = slot.take() {
+                on_update(&EntityUpdate { kind: EntityUpdateKind::Deleted, entity: &entity, changed_props: &changed })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Builds data tables and svc_PacketEntities for the tests of everything built on entities.
+#[cfg(test)]
+pub(crate) mod testing {
+    use protobuf::{RepeatedField, SingularField};
+
+    use super::*;
+    use crate::bitreader::BitWriter;
+    use crate::format::ServerClass;
+    use crate::protos::netmessages::{CSVCMsg_SendTable, CSVCMsg_SendTable_sendprop_t};
+    use crate::sendtables::SPROP_UNSIGNED;
+
+    // Server class name, data table name and its unsigned int props with their bit counts.
+    pub type TestClass<'a> = (&'a str, &'a str, &'a [(&'a str, i32)]);
+
+    pub fn registry(classes: &[TestClass]) -> ServerClassRegistry {
+        let mut registry = ServerClassRegistry::new();
+
+        for (id, (name, datatable, props)) in classes.iter().enumerate() {
+            let props = props.iter().map(|(name, num_bits)| CSVCMsg_SendTable_sendprop_t {
+                field_type: Some(0),
+                var_name: SingularField::some(name.to_string()),
+                flags: Some(SPROP_UNSIGNED),
+                num_bits: Some(*num_bits),
+                ..CSVCMsg_SendTable_sendprop_t::default()
+            });
+            registry.add_send_table(&CSVCMsg_SendTable {
+                net_table_name: SingularField::some(datatable.to_string()),
+                props: RepeatedField::from_vec(props.collect()),
+                ..CSVCMsg_SendTable::default()
+            });
+            registry.add_server_class(ServerClass { id: id as u16, name: name.to_string(), datatable: datatable.to_string() });
+        }
+
+        registry.flatten().unwrap();
+        registry
+    }
+
+    // New style field list setting the named props.
+    pub fn props(writer: BitWriter, class: &FlattenedClass, values: &[(&str, u32)]) -> BitWriter {
+        let mut values: Vec<_> = values.iter().map(|&(name, value)| (class.prop_index(name).unwrap(), value)).collect();
+        values.sort_unstable();
+
+        let mut last = -1;
+        let mut writer = writer.bit(true);
+        for &(index, _) in &values {
+            writer = match index as i64 - last - 1 {
+                0 => writer.bit(true),
+                delta if delta < 8 => writer.bit(false).bit(true).bits(delta as u32, 3),
+                delta => writer.bit(false).bit(false).bits(delta as u32 & 31 | 32, 7).bits(delta as u32 >> 5, 2)
+            };
+            last = index as i64;
+        }
+
+        let writer = writer.bit(false).bit(false).bits(0x7F, 7).bits(0x7F, 7);
+        values.iter().fold(writer, |writer, &(index, value)| writer.bits(value, class.props[index].prop.num_bits as u32))
+    }
+
+    // Entity `index_delta` slots after the previous one enters the PVS.
+    pub fn enter(writer: BitWriter, registry: &ServerClassRegistry, index_delta: u32, class: &str, serial: u32, values: &[(&str, u32)]) -> BitWriter {
+        let class = registry.class_by_name(class).unwrap();
+        let writer = writer.bits(index_delta, 6).bit(false).bit(true).bits(class.id as u32, registry.class_id_bits()).bits(serial, 10);
+        props(writer, class, values)
+    }
+
+    // Delta update of an entity `index_delta` slots after the previous one.
+    pub fn update(writer: BitWriter, class: &FlattenedClass, index_delta: u32, values: &[(&str, u32)]) -> BitWriter {
+        props(writer.bits(index_delta, 6).bit(false).bit(false), class, values)
+    }
+
+    pub fn packet(is_delta: bool, updated_entries: i32, writer: BitWriter) -> CSVCMsg_PacketEntities {
+        CSVCMsg_PacketEntities {
+            is_delta: Some(is_delta),
+            updated_entries: Some(updated_entries),
+            entity_data: SingularField::some(writer.data),
+            ..CSVCMsg_PacketEntities::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+    use crate::bitreader::BitWriter;
+    use crate::players::tests::user_info;
+
+    fn test_registry() -> ServerClassRegistry {
+        registry(&[("CTest", "DT_Test", &[("m_iHealth", 8), ("m_iArmor", 8)])])
+    }
+
+    fn read(entities: &mut EntityTable, registry: &ServerClassRegistry, message: &CSVCMsg_PacketEntities) -> Vec<(EntityUpdateKind, u32)> {
+        let mut updates = Vec::new();
+        entities.read_packet_entities(registry, message, |update| {
+            updates.push((update.kind, update.entity.index));
+            Ok(())
+        }).unwrap();
+        updates
+    }
+
+    #[test]
+    fn full_update_deletes_unlisted_entities() {
+        let registry = test_registry();
+        let mut entities = EntityTable::new();
+
+        let writer = enter(BitWriter::default(), &registry, 1, "CTest", 7, &[("m_iHealth", 100)]);
+        let writer = enter(writer, &registry, 0, "CTest", 3, &[("m_iHealth", 90)]);
+        let updates = read(&mut entities, &registry, &packet(true, 2, writer));
+        assert_eq!(updates, vec![(EntityUpdateKind::Created, 1), (EntityUpdateKind::Created, 2)]);
+
+        let writer = enter(BitWriter::default(), &registry, 2, "CTest", 3, &[("m_iHealth", 80)]);
+        let updates = read(&mut entities, &registry, &packet(false, 1, writer));
+        assert_eq!(updates, vec![(EntityUpdateKind::Created, 2), (EntityUpdateKind::Deleted, 1)]);
+
+        assert!(entities.get(1).is_none());
+        assert_eq!(entities.get(2).and_then(|entity| entity.prop("m_iHealth")), Some(&PropValue::Int(80)));
+    }
+
+    #[test]
+    fn delta_update_keeps_unlisted_entities() {
+        let registry = test_registry();
+        let mut entities = EntityTable::new();
+
+        read(&mut entities, &registry, &packet(true, 1, enter(BitWriter::default(), &registry, 1, "CTest", 7, &[("m_iHealth", 100)])));
+
+        let class = registry.class_by_name("CTest").unwrap();
+        let writer = update(BitWriter::default(), class, 1, &[("m_iArmor", 5)]);
+        let updates = read(&mut entities, &registry, &packet(true, 1, writer));
+        assert_eq!(updates, vec![(EntityUpdateKind::Updated, 1)]);
+
+        let entity = entities.get(1).unwrap();
+        assert_eq!(entity.prop("m_iHealth"), Some(&PropValue::Int(100)));
+        assert_eq!(entity.prop("m_iArmor"), Some(&PropValue::Int(5)));
+    }
+
+    #[test]
+    fn created_entity_starts_from_its_baseline() {
+        let registry = test_registry();
+        let mut entities = EntityTable::new();
+        let class = registry.class_by_name("CTest").unwrap();
+        entities.set_baseline("0", props(BitWriter::default(), class, &[("m_iHealth", 100), ("m_iArmor", 50)]).data);
+
+        let mut changed = Vec::new();
+        let message = packet(true, 1, enter(BitWriter::default(), &registry, 1, "CTest", 7, &[("m_iHealth", 73)]));
+        entities.read_packet_entities(&registry, &message, |update| {
+            changed.extend_from_slice(update.changed_props);
+            Ok(())
+        }).unwrap();
+
+        // Only m_iHealth was sent, m_iArmor comes from the instancebaseline entry.
+        let entity = entities.get(1).unwrap();
+        assert_eq!(changed, vec![0]);
+        assert_eq!(entity.prop("m_iHealth"), Some(&PropValue::Int(73)));
+        assert_eq!(entity.prop("m_iArmor"), Some(&PropValue::Int(50)));
+    }
+
+    #[test]
+    fn handles_pack_the_index_below_the_serial() {
+        let handle = EntityHandle::new(5, 3);
+        assert_eq!(handle, EntityHandle(3 << MAX_EDICT_BITS | 5));
+        assert_eq!((handle.index(), handle.serial()), (5, 3));
+        assert!(handle.is_valid());
+
+        // Out of range parts are masked rather than spilling into each other.
+        let handle = EntityHandle::new(MAX_EDICTS as u32 + 1, 1 << NUM_NETWORKED_EHANDLE_SERIAL_NUMBER_BITS | 2);
+        assert_eq!((handle.index(), handle.serial()), (1, 2));
+
+        assert!(!EntityHandle::INVALID.is_valid());
+        assert_eq!(EntityHandle::from_prop(&PropValue::Int(EntityHandle::INVALID.0 as i32)), None);
+        assert_eq!(EntityHandle::from_prop(&PropValue::Int(handle.0 as i32)), Some(handle));
+    }
+
+    #[test]
+    fn handles_only_resolve_while_the_serial_matches() {
+        let registry = test_registry();
+        let mut entities = EntityTable::new();
+        read(&mut entities, &registry, &packet(true, 1, enter(BitWriter::default(), &registry, 1, "CTest", 7, &[("m_iHealth", 100)])));
+
+        let entity = entities.get(1).unwrap();
+        assert_eq!(entity.handle(), EntityHandle::new(1, 7));
+        assert_eq!(entities.get_by_handle(EntityHandle::new(1, 7)).map(|entity| entity.index), Some(1));
+        assert!(entities.get_by_handle(EntityHandle::INVALID).is_none());
+
+        // The slot is reused by another entity, old handles now point at nothing.
+        read(&mut entities, &registry, &packet(true, 1, enter(BitWriter::default(), &registry, 1, "CTest", 8, &[("m_iHealth", 90)])));
+        assert!(entities.get_by_handle(EntityHandle::new(1, 7)).is_none());
+        assert_eq!(entities.get_by_handle(EntityHandle::new(1, 8)).and_then(|entity| entity.prop("m_iHealth")), Some(&PropValue::Int(90)));
+    }
+
+    #[test]
+    fn finds_players_by_user_id() {
+        let registry = test_registry();
+        let mut entities = EntityTable::new();
+        read(&mut entities, &registry, &packet(true, 1, enter(BitWriter::default(), &registry, 3, "CTest", 1, &[("m_iHealth", 100)])));
+
+        // Userinfo entry 2 is the player in entity slot 3.
+        let mut players = PlayerRoster::new();
+        players.update(2, Some(&user_info(5, 0, "player")), |_| Ok(())).unwrap();
+        players.update(3, Some(&user_info(6, 0, "spectator")), |_| Ok(())).unwrap();
+
+        assert_eq!(entities.find_player_by_user_id(&players, 5).map(|entity| entity.index), Some(3));
+        // Known to the roster but not in the PVS.
+        assert!(entities.find_player_by_user_id(&players, 6).is_none());
+        assert!(entities.find_player_by_user_id(&players, 7).is_none());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fmt, io};
+use std::string::FromUtf8Error;
+
+use protobuf::ProtobufError;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub offset: u64,
+    pub tick: i32,
+    pub command: u8
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DemoError {
+    Truncated { context: ErrorContext },
+    InvalidVarInt { context: ErrorContext },
+    UnknownCommand { command: i32, context: ErrorContext },
+    Protobuf { source: ProtobufError, context: ErrorContext },
+    SizeMismatch { expected: u64, actual: u64, context: ErrorContext },
+    LimitExceeded { size: u64, limit: u64, context: ErrorContext },
+    InvalidData { message: String, context: ErrorContext },
+    Io { source: io::Error, context: ErrorContext },
+    Handler { source: Box<dyn std::error::Error + Send + Sync>, context: ErrorContext }
+}
+
+impl DemoError {
+    pub fn truncated() -> Self {
+        DemoError::Truncated { context: ErrorContext::default() }
+    }
+
+    pub fn invalid_varint() -> Self {
+        DemoError::InvalidVarInt { context: ErrorContext::default() }
+    }
+
+    pub fn unknown_command(command: i32) -> Self {
+        DemoError::UnknownCommand { command, context: ErrorContext::default() }
+    }
+
+    pub fn size_mismatch(expected: u64, actual: u64) -> Self {
+        DemoError::SizeMismatch { expected, actual, context: ErrorContext::default() }
+    }
+
+    pub fn limit_exceeded(size: u64, limit: u64) -> Self {
+        DemoError::LimitExceeded { size, limit, context: ErrorContext::default() }
+    }
+
+    pub fn invalid_data<S: Into<String>>(message: S) -> Self {
+        DemoError::InvalidData { message: message.into(), context: ErrorContext::default() }
+    }
+
+    pub fn handler<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Self {
+        DemoError::Handler { source: error.into(), context: ErrorContext::default() }
+    }
+
+    pub fn context(&self) -> &ErrorContext {
+        use DemoError::*;
+
+        match self {
+            Truncated { context } |
+            InvalidVarInt { context } |
+            UnknownCommand { context, .. } |
+            Protobuf { context, .. } |
+            SizeMismatch { context, .. } |
+            LimitExceeded { context, .. } |
+            InvalidData { context, .. } |
+            Io { context, .. } |
+            Handler { context, .. } => context
+        }
+    }
+
+    pub fn with_context(mut self, context: ErrorContext) -> Self {
+        use DemoError::*;
+
+        match &mut self {
+            Truncated { context: current } |
+            InvalidVarInt { context: current } |
+            UnknownCommand { context: current, .. } |
+            Protobuf { context: current, .. } |
+            SizeMismatch { context: current, .. } |
+            LimitExceeded { context: current, .. } |
+            InvalidData { context: current, .. } |
+            Io { context: current, .. } |
+            Handler { context: current, .. } => *current = context
+        }
+        self
+    }
+}
+
+impl fmt::Display for DemoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use DemoError::*;
+
+        match self {
+            Truncated { .. } => write!(f, "Unexpected end of input")?,
+            InvalidVarInt { .. } => write!(f, "Invalid VarInt")?,
+            UnknownCommand { command, .. } => write!(f, "Invalid Command {}", command)?,
+            Protobuf { source, .. } => write!(f, "Protobuf decode failed: {}", source)?,
+            SizeMismatch { expected, actual, .. } => write!(f, "Size mismatch: expected {}, got {}", expected, actual)?,
+            LimitExceeded { size, limit, .. } => write!(f, "Size {} exceeds limit of {}", size, limit)?,
+            InvalidData { message, .. } => write!(f, "Invalid data: {}", message)?,
+            Io { source, .. } => write!(f, "I/O error: {}", source)?,
+            Handler { source, .. } => write!(f, "Handler error: {}", source)?
+        }
+
+        let context = self.context();
+        write!(f, " (offset {}, tick {}, command {})", context.offset, context.tick, context.command)
+    }
+}
+
+impl std::error::Error for DemoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use DemoError::*;
+
+        match self {
+            Protobuf { source, .. } => Some(source),
+            Io { source, .. } => Some(source),
+            Handler { source, .. } => Some(source.as_ref()),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for DemoError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => DemoError::truncated(),
+            _ => DemoError::Io { source: error, context: ErrorContext::default() }
+        }
+    }
+}
+
+impl From<ProtobufError> for DemoError {
+    fn from(error: ProtobufError) -> Self {
+        DemoError::Protobuf { source: error, context: ErrorContext::default() }
+    }
+}
+
+impl From<bincode::Error> for DemoError {
+    fn from(error: bincode::Error) -> Self {
+        match *error {
+            bincode::ErrorKind::Io(error) => error.into(),
+            error => DemoError::invalid_data(error.to_string())
+        }
+    }
+}
+
+impl From<FromUtf8Error> for DemoError {
+    fn from(error: FromUtf8Error) -> Self {
+        DemoError::invalid_data(error.to_string())
+    }
+}
+
+impl serde::de::Error for DemoError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        DemoError::invalid_data(message.to_string())
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for DemoError {
+    fn from(error: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        DemoError::handler(error)
+    }
+}
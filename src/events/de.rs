@@ -0,0 +1,303 @@
+use std::collections::hash_map;
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::{GameEvent, GameEventValue};
+use crate::{DemoError, Error};
+
+pub struct EventDeserializer<'a>(pub &'a GameEvent);
+
+impl<'de> Deserializer<'de> for EventDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(FieldAccess { fields: self.0.fields.iter(), value: None })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct FieldAccess<'a> {
+    fields: hash_map::Iter<'a, String, GameEventValue>,
+    value: Option<&'a GameEventValue>
+}
+
+impl<'de> MapAccess<'de> for FieldAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.fields.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self.value.take().ok_or_else(|| DemoError::invalid_data("Game event value requested before its key"))?;
+        seed.deserialize(ValueDeserializer(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+// Keys are coerced into whatever the target field asks for where that is lossless
+// enough to be useful: any integer type reads short, long, byte, bool and uint64
+// keys, strings are parsed as numbers and numbers are formatted as strings.
+struct ValueDeserializer<'a>(&'a GameEventValue);
+
+impl<'a> ValueDeserializer<'a> {
+    fn unexpected(&self) -> Unexpected<'a> {
+        match self.0 {
+            GameEventValue::String(value) => Unexpected::Str(value),
+            GameEventValue::Float(value) => Unexpected::Float(*value as f64),
+            GameEventValue::Long(value) | GameEventValue::Short(value) | GameEventValue::Byte(value) => Unexpected::Signed(*value as i64),
+            GameEventValue::Bool(value) => Unexpected::Bool(*value),
+            GameEventValue::UInt64(value) => Unexpected::Unsigned(*value),
+            GameEventValue::WString(value) => Unexpected::Bytes(value)
+        }
+    }
+
+    fn invalid_type<'de, V: Visitor<'de>>(&self, visitor: &V) -> Error {
+        de::Error::invalid_type(self.unexpected(), visitor)
+    }
+
+    fn deserialize_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            GameEventValue::UInt64(value) => visitor.visit_u64(*value),
+            GameEventValue::String(value) => {
+                let value = value.trim();
+                if let Ok(value) = value.parse() {
+                    visitor.visit_i64(value)
+                } else if let Ok(value) = value.parse() {
+                    visitor.visit_u64(value)
+                } else {
+                    Err(self.invalid_type(&visitor))
+                }
+            },
+            value => match value.as_i32() {
+                Some(value) => visitor.visit_i64(value as i64),
+                None => Err(self.invalid_type(&visitor))
+            }
+        }
+    }
+}
+
+macro_rules! deserialize_integers {
+    ($($method:ident)+) => ($(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            self.deserialize_integer(visitor)
+        }
+    )+);
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            GameEventValue::String(value) => visitor.visit_borrowed_str(value),
+            GameEventValue::Float(value) => visitor.visit_f32(*value),
+            GameEventValue::Long(value) | GameEventValue::Short(value) | GameEventValue::Byte(value) => visitor.visit_i32(*value),
+            GameEventValue::Bool(value) => visitor.visit_bool(*value),
+            GameEventValue::UInt64(value) => visitor.visit_u64(*value),
+            GameEventValue::WString(value) => visitor.visit_borrowed_bytes(value)
+        }
+    }
+
+    deserialize_integers! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let value = match self.0 {
+            GameEventValue::String(value) => match value.trim() {
+                "1" | "true" => Some(true),
+                "0" | "false" => Some(false),
+                _ => None
+            },
+            value => value.as_bool()
+        };
+
+        match value {
+            Some(value) => visitor.visit_bool(value),
+            None => Err(self.invalid_type(&visitor))
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let value = match self.0 {
+            GameEventValue::String(value) => value.trim().parse().ok(),
+            GameEventValue::UInt64(value) => Some(*value as f64),
+            value => value.as_f32().map(f64::from)
+        };
+
+        match value {
+            Some(value) => visitor.visit_f64(value),
+            None => Err(self.invalid_type(&visitor))
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            GameEventValue::String(value) => visitor.visit_borrowed_str(value),
+            GameEventValue::Float(value) => visitor.visit_string(value.to_string()),
+            GameEventValue::Long(value) | GameEventValue::Short(value) | GameEventValue::Byte(value) => visitor.visit_string(value.to_string()),
+            GameEventValue::Bool(value) => visitor.visit_string(value.to_string()),
+            GameEventValue::UInt64(value) => visitor.visit_string(value.to_string()),
+            GameEventValue::WString(value) => match std::str::from_utf8(value) {
+                Ok(value) => visitor.visit_borrowed_str(value),
+                Err(_) => Err(self.invalid_type(&visitor))
+            }
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            GameEventValue::String(value) => visitor.visit_borrowed_bytes(value.as_bytes()),
+            GameEventValue::WString(value) => visitor.visit_borrowed_bytes(value),
+            _ => Err(self.invalid_type(&visitor))
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    // Keys missing from the event are left to the struct's own defaults.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 char unit unit_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::de::DeserializeOwned;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Field<T> {
+        value: T
+    }
+
+    fn event(fields: Vec<(&str, GameEventValue)>) -> GameEvent {
+        GameEvent {
+            event_id: 0,
+            name: "test".to_owned(),
+            fields: fields.into_iter().map(|(key, value)| (key.to_owned(), value)).collect::<HashMap<_, _>>()
+        }
+    }
+
+    fn coerce<T: DeserializeOwned>(value: GameEventValue) -> Result<T, Error> {
+        event(vec![("value", value)]).deserialize::<Field<T>>().map(|field| field.value)
+    }
+
+    fn string(value: &str) -> GameEventValue {
+        GameEventValue::String(value.to_owned())
+    }
+
+    #[test]
+    fn coerces_into_integers() {
+        assert_eq!(coerce::<i32>(GameEventValue::Short(-3)).unwrap(), -3);
+        assert_eq!(coerce::<i64>(GameEventValue::Long(70000)).unwrap(), 70000);
+        assert_eq!(coerce::<u8>(GameEventValue::Byte(200)).unwrap(), 200);
+        assert_eq!(coerce::<i32>(GameEventValue::Bool(true)).unwrap(), 1);
+        assert_eq!(coerce::<u64>(GameEventValue::UInt64(76561197960265728)).unwrap(), 76561197960265728);
+        assert_eq!(coerce::<i32>(string(" 42 ")).unwrap(), 42);
+        assert_eq!(coerce::<u64>(string("18446744073709551615")).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn rejects_integers_it_cannot_represent() {
+        assert!(coerce::<i32>(GameEventValue::Float(1.5)).is_err());
+        assert!(coerce::<i32>(string("twelve")).is_err());
+        assert!(coerce::<i32>(GameEventValue::WString(b"1".to_vec())).is_err());
+        // Out of range for the target type.
+        assert!(coerce::<u8>(GameEventValue::Long(300)).is_err());
+        assert!(coerce::<u32>(GameEventValue::Short(-1)).is_err());
+        assert!(coerce::<i64>(GameEventValue::UInt64(u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn coerces_into_floats() {
+        assert_eq!(coerce::<f32>(GameEventValue::Float(0.25)).unwrap(), 0.25);
+        assert_eq!(coerce::<f32>(GameEventValue::Long(-8)).unwrap(), -8.0);
+        assert_eq!(coerce::<f64>(GameEventValue::UInt64(3)).unwrap(), 3.0);
+        assert_eq!(coerce::<f32>(GameEventValue::Bool(true)).unwrap(), 1.0);
+        assert_eq!(coerce::<f32>(string("2.5")).unwrap(), 2.5);
+
+        assert!(coerce::<f32>(string("far")).is_err());
+        assert!(coerce::<f32>(GameEventValue::WString(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn coerces_into_bools() {
+        assert!(coerce::<bool>(GameEventValue::Bool(true)).unwrap());
+        assert!(coerce::<bool>(GameEventValue::Byte(1)).unwrap());
+        assert!(!coerce::<bool>(GameEventValue::Short(0)).unwrap());
+        assert!(coerce::<bool>(string("true")).unwrap());
+        assert!(!coerce::<bool>(string("0")).unwrap());
+
+        assert!(coerce::<bool>(string("yes")).is_err());
+        assert!(coerce::<bool>(GameEventValue::Float(1.0)).is_err());
+        assert!(coerce::<bool>(GameEventValue::UInt64(1)).is_err());
+    }
+
+    #[test]
+    fn coerces_into_strings() {
+        assert_eq!(coerce::<String>(string("ak47")).unwrap(), "ak47");
+        assert_eq!(coerce::<String>(GameEventValue::Short(7)).unwrap(), "7");
+        assert_eq!(coerce::<String>(GameEventValue::Float(1.5)).unwrap(), "1.5");
+        assert_eq!(coerce::<String>(GameEventValue::Bool(false)).unwrap(), "false");
+        assert_eq!(coerce::<String>(GameEventValue::UInt64(9)).unwrap(), "9");
+        assert_eq!(coerce::<String>(GameEventValue::WString(b"name".to_vec())).unwrap(), "name");
+
+        assert!(coerce::<String>(GameEventValue::WString(vec![0xFF])).is_err());
+    }
+
+    #[test]
+    fn coerces_into_options() {
+        assert_eq!(coerce::<Option<i32>>(GameEventValue::Byte(5)).unwrap(), Some(5));
+        assert!(coerce::<Option<i32>>(string("five")).is_err());
+
+        let missing: Field<Option<i32>> = event(vec![]).deserialize().unwrap();
+        assert_eq!(missing.value, None);
+    }
+
+    #[test]
+    fn rejects_missing_required_fields() {
+        assert!(event(vec![]).deserialize::<Field<i32>>().is_err());
+        assert!(event(vec![("other", GameEventValue::Long(1))]).deserialize::<Field<String>>().is_err());
+    }
+}
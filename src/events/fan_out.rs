@@ -0,0 +1,560 @@
+use std::any::type_name;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::*;
+
+// Each list macro hands every method of its trait to `$callback`, preceded by `$args`.
+macro_rules! event_handler_methods {
+    ($callback:ident $args:tt) => {
+        $callback! {
+            $args
+            on_dem_header => DemHeader;
+            on_packet_info => PacketInfo;
+            on_server_class => ServerClass;
+            on_server_class_registry => ServerClassRegistry;
+            on_string_tables => StringTablesSnapshot;
+            on_console_cmd => ConsoleCommand;
+            on_user_cmd => UserCmd;
+            on_custom_data => CustomData;
+            on_truncated => TruncatedDemo;
+            on_roster_change => RosterChange<'_>;
+            on_string_table_entry => StringTableEntryUpdate<'_>;
+            on_entity_update => EntityUpdate<'_>;
+            on_tick_start => TickStart;
+            on_tick_end => TickEnd<'_>;
+
+            on_nop => CNETMsg_NOP;
+            on_disconnect => CNETMsg_Disconnect;
+            on_file => CNETMsg_File;
+            on_split_screen_user => CNETMsg_SplitScreenUser;
+            on_tick => CNETMsg_Tick;
+            on_string_cmd => CNETMsg_StringCmd;
+            on_set_con_var => CNETMsg_SetConVar;
+            on_signon_state => CNETMsg_SignonState;
+            on_player_avatar_data => CNETMsg_PlayerAvatarData;
+
+            on_server_info => CSVCMsg_ServerInfo;
+            on_send_table => CSVCMsg_SendTable;
+            on_class_info => CSVCMsg_ClassInfo;
+            on_set_pause => CSVCMsg_SetPause;
+            on_create_string_table => CSVCMsg_CreateStringTable;
+            on_update_string_table => CSVCMsg_UpdateStringTable;
+            on_voice_init => CSVCMsg_VoiceInit;
+            on_voice_data => CSVCMsg_VoiceData;
+            on_print => CSVCMsg_Print;
+            on_sounds => CSVCMsg_Sounds;
+            on_set_view => CSVCMsg_SetView;
+            on_fix_angle => CSVCMsg_FixAngle;
+            on_crosshair_angle => CSVCMsg_CrosshairAngle;
+            on_bspdecal => CSVCMsg_BSPDecal;
+            on_split_screen => CSVCMsg_SplitScreen;
+            on_user_message => CSVCMsg_UserMessage;
+            on_entity_message => CSVCMsg_EntityMsg;
+            on_game_event => CSVCMsg_GameEvent;
+            on_packet_entities => CSVCMsg_PacketEntities;
+            on_temp_entities => CSVCMsg_TempEntities;
+            on_prefetch => CSVCMsg_Prefetch;
+            on_menu => CSVCMsg_Menu;
+            on_game_event_list => CSVCMsg_GameEventList;
+            on_get_cvar_value => CSVCMsg_GetCvarValue;
+            on_paintmap_data => CSVCMsg_PaintmapData;
+            on_cmd_key_values => CSVCMsg_CmdKeyValues;
+            on_encrypted_data => CSVCMsg_EncryptedData;
+            on_hltv_replay => CSVCMsg_HltvReplay;
+            on_broadcast_command => CSVCMsg_Broadcast_Command
+        }
+    };
+}
+
+macro_rules! user_message_handler_methods {
+    ($callback:ident $args:tt) => {
+        $callback! {
+            $args
+            on_vguimenu => CCSUsrMsg_VGUIMenu;
+            on_geiger => CCSUsrMsg_Geiger;
+            on_train => CCSUsrMsg_Train;
+            on_hud_text => CCSUsrMsg_HudText;
+            on_say_text => CCSUsrMsg_SayText;
+            on_say_text2 => CCSUsrMsg_SayText2;
+            on_text_msg => CCSUsrMsg_TextMsg;
+            on_hud_msg => CCSUsrMsg_HudMsg;
+            on_reset_hud => CCSUsrMsg_ResetHud;
+            on_game_title => CCSUsrMsg_GameTitle;
+            on_shake => CCSUsrMsg_Shake;
+            on_fade => CCSUsrMsg_Fade;
+            on_rumble => CCSUsrMsg_Rumble;
+            on_close_caption => CCSUsrMsg_CloseCaption;
+            on_close_caption_direct => CCSUsrMsg_CloseCaptionDirect;
+            on_send_audio => CCSUsrMsg_SendAudio;
+            on_raw_audio => CCSUsrMsg_RawAudio;
+            on_voice_mask => CCSUsrMsg_VoiceMask;
+            on_request_state => CCSUsrMsg_RequestState;
+            on_damage => CCSUsrMsg_Damage;
+            on_radio_text => CCSUsrMsg_RadioText;
+            on_hint_text => CCSUsrMsg_HintText;
+            on_key_hint_text => CCSUsrMsg_KeyHintText;
+            on_process_spotted_entity_update => CCSUsrMsg_ProcessSpottedEntityUpdate;
+            on_reload_effect => CCSUsrMsg_ReloadEffect;
+            on_adjust_money => CCSUsrMsg_AdjustMoney;
+            on_update_team_money => UpdateTeamMoney<'_>;
+            on_stop_spectator_mode => CCSUsrMsg_StopSpectatorMode;
+            on_kill_cam => CCSUsrMsg_KillCam;
+            on_desired_timescale => CCSUsrMsg_DesiredTimescale;
+            on_current_timescale => CCSUsrMsg_CurrentTimescale;
+            on_achievement_event => CCSUsrMsg_AchievementEvent;
+            on_match_end_conditions => CCSUsrMsg_MatchEndConditions;
+            on_disconnect_to_lobby => CCSUsrMsg_DisconnectToLobby;
+            on_player_stats_update => CCSUsrMsg_PlayerStatsUpdate;
+            on_display_inventory => CCSUsrMsg_DisplayInventory;
+            on_warmup_has_ended => CCSUsrMsg_WarmupHasEnded;
+            on_client_info => CCSUsrMsg_ClientInfo;
+            on_xrank_get => CCSUsrMsg_XRankGet;
+            on_xrank_upd => CCSUsrMsg_XRankUpd;
+            on_call_vote_failed => CCSUsrMsg_CallVoteFailed;
+            on_vote_start => CCSUsrMsg_VoteStart;
+            on_vote_pass => CCSUsrMsg_VotePass;
+            on_vote_failed => CCSUsrMsg_VoteFailed;
+            on_vote_setup => CCSUsrMsg_VoteSetup;
+            on_server_rank_reveal_all => CCSUsrMsg_ServerRankRevealAll;
+            on_send_last_killer_damage_to_client => CCSUsrMsg_SendLastKillerDamageToClient;
+            on_server_rank_update => CCSUsrMsg_ServerRankUpdate;
+            on_item_pickup => CCSUsrMsg_ItemPickup;
+            on_show_menu => CCSUsrMsg_ShowMenu;
+            on_bar_time => CCSUsrMsg_BarTime;
+            on_ammo_denied => CCSUsrMsg_AmmoDenied;
+            on_mark_achievement => CCSUsrMsg_MarkAchievement;
+            on_match_stats_update => CCSUsrMsg_MatchStatsUpdate;
+            on_item_drop => CCSUsrMsg_ItemDrop;
+            on_glow_prop_turn_off => CCSUsrMsg_GlowPropTurnOff;
+            on_send_player_item_drops => CCSUsrMsg_SendPlayerItemDrops;
+            on_round_backup_filenames => CCSUsrMsg_RoundBackupFilenames;
+            on_send_player_item_found => CCSUsrMsg_SendPlayerItemFound;
+            on_report_hit => CCSUsrMsg_ReportHit;
+            on_xp_update => CCSUsrMsg_XpUpdate;
+            on_quest_progress => CCSUsrMsg_QuestProgress;
+            on_score_leaderboard_data => CCSUsrMsg_ScoreLeaderboardData;
+            on_player_decal_digital_signature => CCSUsrMsg_PlayerDecalDigitalSignature;
+            on_weapon_sound => CCSUsrMsg_WeaponSound;
+            on_update_screen_health_bar => CCSUsrMsg_UpdateScreenHealthBar;
+            on_entity_outline_highlight => CCSUsrMsg_EntityOutlineHighlight;
+            on_ssui => CCSUsrMsg_SSUI;
+            on_survival_stats => CCSUsrMsg_SurvivalStats;
+            on_disconnect_to_lobby2 => CCSUsrMsg_DisconnectToLobby;
+            on_end_of_match_all_players_data => CCSUsrMsg_EndOfMatchAllPlayersData;
+            on_round_impact_score_data => CCSUsrMsg_RoundImpactScoreData;
+            on_current_round_odds => CCSUsrMsg_CurrentRoundOdds;
+            on_deep_stats => CCSUsrMsg_DeepStats;
+
+            on_unknown_user_message => UnknownUserMessage<'_>
+        }
+    };
+}
+
+macro_rules! game_event_handler_methods {
+    ($callback:ident $args:tt) => {
+        $callback! {
+            $args
+            on_decoded_game_event => GameEvent;
+
+            on_player_death => PlayerDeath;
+            on_player_hurt => PlayerHurt;
+            on_player_blind => PlayerBlind;
+            on_player_spawn => PlayerSpawn;
+            on_player_team => PlayerTeam;
+            on_player_disconnect => PlayerDisconnect;
+            on_weapon_fire => WeaponFire;
+            on_item_purchase => ItemPurchase;
+            on_round_start => RoundStart;
+            on_round_freeze_end => RoundFreezeEnd;
+            on_round_end => RoundEnd;
+            on_round_mvp => RoundMvp;
+            on_bomb_planted => BombPlanted;
+            on_bomb_defused => BombDefused;
+            on_bomb_exploded => BombExploded;
+            on_hegrenade_detonate => HeGrenadeDetonate;
+            on_flashbang_detonate => FlashbangDetonate;
+            on_smokegrenade_detonate => SmokeGrenadeDetonate
+        }
+    };
+}
+
+macro_rules! prop_change_handler_methods {
+    ($callback:ident $args:tt) => {
+        $callback! {
+            $args
+            on_prop_change => PropChange<'_>
+        }
+    };
+}
+
+macro_rules! forward_to_deref {
+    (() $($ident:ident => $ty:ty);+) => ($(
+        fn $ident(&mut self, event: &$ty, context: &ParserContext) -> Result<Control, Error> {
+            (**self).$ident(event, context)
+        }
+    )+);
+}
+
+// Events outside of packets still reach handlers that skipped the current packet.
+macro_rules! skippable {
+    (on_tick_start) => (false);
+    (on_tick_end) => (false);
+    (on_truncated) => (false);
+    ($ident:ident) => (true);
+}
+
+// The header is the first event of every parse, members start over from it.
+macro_rules! starts_parse {
+    (on_dem_header) => (true);
+    ($ident:ident) => (false);
+}
+
+macro_rules! fan_out_iter {
+    (() $($ident:ident => $ty:ty);+) => ($(
+        fn $ident(&mut self, event: &$ty, context: &ParserContext) -> Result<Control, Error> {
+            let key = Members::key(self);
+            if starts_parse!($ident) {
+                Members::forget(key);
+            }
+
+            for (index, handler) in self.iter_mut().enumerate() {
+                if Members::receives(key, index, context, skippable!($ident)) {
+                    let control = handler.$ident(event, context)?;
+                    Members::record(key, index, control, context);
+                }
+            }
+            Ok(Members::combined_control(key, self.len(), context))
+        }
+    )+);
+}
+
+macro_rules! fan_out_tuple {
+    ($indices:tt $($ident:ident => $ty:ty);+) => ($(
+        fn $ident(&mut self, event: &$ty, context: &ParserContext) -> Result<Control, Error> {
+            fan_out_fields!(self, event, context, $ident, $indices)
+        }
+    )+);
+}
+
+macro_rules! fan_out_fields {
+    ($self:ident, $event:ident, $context:ident, $ident:ident, ($($index:tt),+)) => {{
+        let key = Members::key($self);
+        if starts_parse!($ident) {
+            Members::forget(key);
+        }
+
+        $(
+            if Members::receives(key, $index, $context, skippable!($ident)) {
+                let control = $self.$index.$ident($event, $context)?;
+                Members::record(key, $index, control, $context);
+            }
+        )+
+        Ok(Members::combined_control(key, [$($index),+].len(), $context))
+    }};
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Member {
+    stopped: bool,
+    // The frame of the packet the handler skipped.
+    skipped_frame: Option<u32>
+}
+
+impl Member {
+    fn receives(&self, context: &ParserContext, skippable: bool) -> bool {
+        !self.stopped && (!skippable || self.skipped_frame != Some(context.frame))
+    }
+}
+
+// Tuples and Vecs have no room for what their members returned, so it is kept here by the
+// address and type of the tuple or Vec. The type tells apart a tuple from the first of its
+// fields, which shares its address. Moving the handlers in the middle of a parse forgets
+// which of them stopped.
+type MembersKey = (usize, &'static str);
+
+thread_local! {
+    static MEMBERS: RefCell<HashMap<MembersKey, Vec<Member>>> = RefCell::new(HashMap::new());
+}
+
+// The members are never borrowed across a call into a handler, which may be a tuple itself.
+struct Members;
+
+impl Members {
+    fn key<T>(handlers: &T) -> MembersKey {
+        (handlers as *const T as usize, type_name::<T>())
+    }
+
+    fn forget(key: MembersKey) {
+        MEMBERS.with(|members| members.borrow_mut().remove(&key));
+    }
+
+    fn get(key: MembersKey, index: usize) -> Member {
+        MEMBERS.with(|members| members.borrow().get(&key).and_then(|members| members.get(index).copied()).unwrap_or_default())
+    }
+
+    fn receives(key: MembersKey, index: usize, context: &ParserContext, skippable: bool) -> bool {
+        Members::get(key, index).receives(context, skippable)
+    }
+
+    fn record(key: MembersKey, index: usize, control: Control, context: &ParserContext) {
+        if control == Control::Continue {
+            return;
+        }
+
+        MEMBERS.with(|members| {
+            let mut members = members.borrow_mut();
+            let members = members.entry(key).or_default();
+            if members.len() <= index {
+                members.resize(index + 1, Member::default());
+            }
+
+            match control {
+                Control::Continue => {},
+                Control::SkipPacket => members[index].skipped_frame = Some(context.frame),
+                Control::Stop => members[index].stopped = true
+            }
+        });
+    }
+
+    // The parse stops once every member stopped and a packet is skipped once every member skips it.
+    fn combined_control(key: MembersKey, len: usize, context: &ParserContext) -> Control {
+        let members: Vec<_> = (0..len).map(|index| Members::get(key, index)).collect();
+
+        if members.is_empty() {
+            Control::Continue
+        } else if members.iter().all(|member| member.stopped) {
+            Control::Stop
+        } else if members.iter().all(|member| !member.receives(context, true)) {
+            Control::SkipPacket
+        } else {
+            Control::Continue
+        }
+    }
+
+    fn wanted_by(key: MembersKey, index: usize) -> bool {
+        !Members::get(key, index).stopped
+    }
+}
+
+impl<T: EventHandler + ?Sized> EventHandler for &mut T {
+    event_handler_methods!(forward_to_deref ());
+
+    fn wants(&self, message: &MessageKind<'_>) -> bool {
+        (**self).wants(message)
+    }
+}
+
+impl<T: UserMessageEventHandler + ?Sized> UserMessageEventHandler for &mut T {
+    user_message_handler_methods!(forward_to_deref ());
+}
+
+impl<T: GameEventHandler + ?Sized> GameEventHandler for &mut T {
+    game_event_handler_methods!(forward_to_deref ());
+}
+
+impl<T: PropChangeHandler + ?Sized> PropChangeHandler for &mut T {
+    prop_change_handler_methods!(forward_to_deref ());
+}
+
+impl<T: EventHandler + ?Sized> EventHandler for Box<T> {
+    event_handler_methods!(forward_to_deref ());
+
+    fn wants(&self, message: &MessageKind<'_>) -> bool {
+        (**self).wants(message)
+    }
+}
+
+impl<T: UserMessageEventHandler + ?Sized> UserMessageEventHandler for Box<T> {
+    user_message_handler_methods!(forward_to_deref ());
+}
+
+impl<T: GameEventHandler + ?Sized> GameEventHandler for Box<T> {
+    game_event_handler_methods!(forward_to_deref ());
+}
+
+impl<T: PropChangeHandler + ?Sized> PropChangeHandler for Box<T> {
+    prop_change_handler_methods!(forward_to_deref ());
+}
+
+// Tuples and Vecs dispatch every event to each of their handlers in order. Together with
+// the Box impls this covers Vec<Box<dyn EventHandler>> and friends.
+//
+// Handlers control only themselves: one that returned Stop gets no further events and one
+// that returned SkipPacket none for the rest of that packet.
+impl<T: EventHandler> EventHandler for Vec<T> {
+    event_handler_methods!(fan_out_iter ());
+
+    fn wants(&self, message: &MessageKind<'_>) -> bool {
+        let key = Members::key(self);
+        self.iter().enumerate().any(|(index, handler)| Members::wanted_by(key, index) && handler.wants(message))
+    }
+}
+
+impl<T: UserMessageEventHandler> UserMessageEventHandler for Vec<T> {
+    user_message_handler_methods!(fan_out_iter ());
+}
+
+impl<T: GameEventHandler> GameEventHandler for Vec<T> {
+    game_event_handler_methods!(fan_out_iter ());
+}
+
+impl<T: PropChangeHandler> PropChangeHandler for Vec<T> {
+    prop_change_handler_methods!(fan_out_iter ());
+}
+
+macro_rules! impl_fan_out_tuples {
+    ($(($($name:ident $index:tt),+))+) => ($(
+        impl<$($name: EventHandler),+> EventHandler for ($($name,)+) {
+            event_handler_methods!(fan_out_tuple ($($index),+));
+
+            fn wants(&self, message: &MessageKind<'_>) -> bool {
+                let key = Members::key(self);
+                $((Members::wanted_by(key, $index) && self.$index.wants(message)))||+
+            }
+        }
+
+        impl<$($name: UserMessageEventHandler),+> UserMessageEventHandler for ($($name,)+) {
+            user_message_handler_methods!(fan_out_tuple ($($index),+));
+        }
+
+        impl<$($name: GameEventHandler),+> GameEventHandler for ($($name,)+) {
+            game_event_handler_methods!(fan_out_tuple ($($index),+));
+        }
+
+        impl<$($name: PropChangeHandler),+> PropChangeHandler for ($($name,)+) {
+            prop_change_handler_methods!(fan_out_tuple ($($index),+));
+        }
+    )+);
+}
+
+impl_fan_out_tuples! {
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns `control` from every console command and net_Tick it sees.
+    #[derive(Default)]
+    struct Recorder {
+        control: Control,
+        seen: Vec<&'static str>
+    }
+
+    impl Recorder {
+        fn returning(control: Control) -> Self {
+            Recorder { control, seen: Vec::new() }
+        }
+    }
+
+    impl EventHandler for Recorder {
+        fn on_console_cmd(&mut self, _: &ConsoleCommand, _: &ParserContext) -> Result<Control, Error> {
+            self.seen.push("console_cmd");
+            Ok(self.control)
+        }
+
+        fn on_tick(&mut self, _: &CNETMsg_Tick, _: &ParserContext) -> Result<Control, Error> {
+            self.seen.push("tick");
+            Ok(self.control)
+        }
+
+        fn on_tick_start(&mut self, _: &TickStart, _: &ParserContext) -> Result<Control, Error> {
+            self.seen.push("tick_start");
+            Ok(Control::Continue)
+        }
+    }
+
+    fn frame(frame: u32) -> ParserContext {
+        ParserContext { frame, ..ParserContext::default() }
+    }
+
+    fn console_cmd() -> ConsoleCommand {
+        ConsoleCommand { tick: 0, player_slot: 0, command: String::new() }
+    }
+
+    // Every parse starts with the header, which makes the members forget earlier parses.
+    fn header() -> DemHeader {
+        DemHeader {
+            magic: *b"HL2DEMO\0",
+            demo_protocol: 4,
+            network_protocol: 0,
+            server_name: [0; 260],
+            client_name: [0; 260],
+            map_name: [0; 260],
+            game_directory: [0; 260],
+            playback_time: 0.0,
+            playback_ticks: 0,
+            playback_frames: 0,
+            signon_length: 0
+        }
+    }
+
+    #[test]
+    fn stop_only_ends_the_member_that_returned_it() {
+        let mut handlers = (Recorder::returning(Control::Stop), Recorder::default());
+        handlers.dispatch(&header(), &frame(0)).unwrap();
+
+        assert_eq!(handlers.dispatch(&console_cmd(), &frame(0)).unwrap(), Control::Continue);
+        assert_eq!(handlers.dispatch(&console_cmd(), &frame(1)).unwrap(), Control::Continue);
+        assert_eq!(handlers.0.seen, vec!["console_cmd"]);
+        assert_eq!(handlers.1.seen, vec!["console_cmd", "console_cmd"]);
+
+        handlers.1.control = Control::Stop;
+        assert_eq!(handlers.dispatch(&console_cmd(), &frame(2)).unwrap(), Control::Stop);
+    }
+
+    #[test]
+    fn skip_packet_only_hides_the_packet_from_the_member_that_returned_it() {
+        let mut handlers = vec![Recorder::returning(Control::SkipPacket), Recorder::default()];
+        handlers.dispatch(&header(), &frame(0)).unwrap();
+
+        assert_eq!(handlers.dispatch(&CNETMsg_Tick::default(), &frame(0)).unwrap(), Control::Continue);
+        assert_eq!(handlers.dispatch(&CNETMsg_Tick::default(), &frame(0)).unwrap(), Control::Continue);
+        assert_eq!(handlers.dispatch(&TickStart { tick: 1 }, &frame(0)).unwrap(), Control::Continue);
+        assert_eq!(handlers.dispatch(&CNETMsg_Tick::default(), &frame(1)).unwrap(), Control::Continue);
+        assert_eq!(handlers[0].seen, vec!["tick", "tick_start", "tick"]);
+        assert_eq!(handlers[1].seen, vec!["tick", "tick", "tick_start", "tick"]);
+
+        handlers[1].control = Control::SkipPacket;
+        assert_eq!(handlers.dispatch(&CNETMsg_Tick::default(), &frame(2)).unwrap(), Control::SkipPacket);
+    }
+
+    #[test]
+    fn skip_packet_of_the_last_running_member_skips_the_packet() {
+        let mut handlers = (Recorder::returning(Control::Stop), Recorder::returning(Control::SkipPacket));
+        handlers.dispatch(&header(), &frame(0)).unwrap();
+
+        assert_eq!(handlers.dispatch(&CNETMsg_Tick::default(), &frame(0)).unwrap(), Control::SkipPacket);
+    }
+
+    #[test]
+    fn nested_handlers_keep_their_own_state() {
+        let mut handlers = ((Recorder::returning(Control::Stop), Recorder::default()), Recorder::default());
+        handlers.dispatch(&header(), &frame(0)).unwrap();
+
+        assert_eq!(handlers.dispatch(&console_cmd(), &frame(0)).unwrap(), Control::Continue);
+        assert_eq!(handlers.dispatch(&console_cmd(), &frame(1)).unwrap(), Control::Continue);
+        assert_eq!(handlers.0.0.seen, vec!["console_cmd"]);
+        assert_eq!(handlers.0.1.seen, vec!["console_cmd", "console_cmd"]);
+        assert_eq!(handlers.1.seen, vec!["console_cmd", "console_cmd"]);
+    }
+
+    #[test]
+    fn stopped_members_start_over_with_the_next_parse() {
+        let mut handlers: Vec<Box<dyn EventHandler>> = vec![Box::new(Recorder::returning(Control::Stop))];
+        handlers.dispatch(&header(), &frame(0)).unwrap();
+
+        assert_eq!(handlers.dispatch(&console_cmd(), &frame(0)).unwrap(), Control::Stop);
+        assert!(!handlers.wants(&MessageKind::Net(NET_Messages::net_Tick)));
+
+        handlers.dispatch(&header(), &frame(0)).unwrap();
+        assert!(handlers.wants(&MessageKind::Net(NET_Messages::net_Tick)));
+        assert_eq!(handlers.dispatch(&console_cmd(), &frame(0)).unwrap(), Control::Stop);
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use super::de::EventDeserializer;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum GameEventValue {
+    String(String),
+    Float(f32),
+    Long(i32),
+    Short(i32),
+    Byte(i32),
+    Bool(bool),
+    UInt64(u64),
+    WString(Vec<u8>)
+}
+
+impl GameEventValue {
+    fn from_key(key: &CSVCMsg_GameEvent_key_t) -> Option<Self> {
+        match key.get_field_type() {
+            1 => Some(GameEventValue::String(key.get_val_string().to_owned())),
+            2 => Some(GameEventValue::Float(key.get_val_float())),
+            3 => Some(GameEventValue::Long(key.get_val_long())),
+            4 => Some(GameEventValue::Short(key.get_val_short())),
+            5 => Some(GameEventValue::Byte(key.get_val_byte())),
+            6 => Some(GameEventValue::Bool(key.get_val_bool())),
+            7 => Some(GameEventValue::UInt64(key.get_val_uint64())),
+            8 => Some(GameEventValue::WString(key.get_val_wstring().to_owned())),
+            _ => None
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            GameEventValue::Long(value) | GameEventValue::Short(value) | GameEventValue::Byte(value) => Some(*value),
+            GameEventValue::Bool(value) => Some(*value as i32),
+            _ => None
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            GameEventValue::Float(value) => Some(*value),
+            value => value.as_i32().map(|value| value as f32)
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            GameEventValue::Bool(value) => Some(*value),
+            value => value.as_i32().map(|value| value != 0)
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            GameEventValue::UInt64(value) => Some(*value),
+            value => value.as_i32().map(|value| value as u64)
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GameEventValue::String(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GameEvent {
+    pub event_id: i32,
+    pub name: String,
+    pub fields: HashMap<String, GameEventValue>
+}
+
+impl GameEvent {
+    // Pairs the event's positional keys with the key names of its descriptor.
+    pub fn decode(descriptor: &CSVCMsg_GameEventList_descriptor_t, event: &CSVCMsg_GameEvent) -> Self {
+        let fields = descriptor.get_keys().iter()
+            .zip(event.get_keys())
+            .filter_map(|(descriptor_key, key)| {
+                GameEventValue::from_key(key).map(|value| (descriptor_key.get_name().to_owned(), value))
+            })
+            .collect();
+
+        GameEvent {
+            event_id: event.get_eventid(),
+            name: descriptor.get_name().to_owned(),
+            fields
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&GameEventValue> {
+        self.fields.get(key)
+    }
+
+    // Maps the event's keys onto the fields of T by name, see de.rs for the coercions.
+    pub fn deserialize<'a, T: Deserialize<'a>>(&'a self) -> Result<T, Error> {
+        T::deserialize(EventDeserializer(self))
+    }
+}
+
+// Turns a GameEvent into the typed events and the types #[demo_handler] methods take,
+// so both follow the coercions in de.rs. Keys missing from the event take the default
+// of their field.
+pub trait FromGameEvent: Sized {
+    fn from_game_event(event: &GameEvent) -> Result<Self, Error>;
+}
+
+impl<T: DeserializeOwned> FromGameEvent for T {
+    fn from_game_event(event: &GameEvent) -> Result<Self, Error> {
+        event.deserialize()
+    }
+}
+
+macro_rules! game_events {
+    ($($name:literal => $ident:ident { $($field:ident: $ty:ty = $key:literal),* });+) => {
+        $(
+            #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+            #[serde(default)]
+            pub struct $ident {
+                $(
+                    #[serde(rename = $key)]
+                    pub $field: $ty
+                ),*
+            }
+        )+
+
+        const TYPED_GAME_EVENTS: &[&str] = &[$($name),+];
+
+        fn dispatch_typed<T: GameEventHandler>(handler: &mut T, event: &GameEvent, context: &ParserContext) -> Result<Control, Error> {
+            match event.name.as_str() {
+                $($name => handler.dispatch(&$ident::from_game_event(event)?, context),)+
+                _ => Ok(Control::Continue)
+            }
+        }
+    };
+}
+
+game_events! {
+    "player_death" => PlayerDeath {
+        user_id: i32 = "userid",
+        attacker: i32 = "attacker",
+        assister: i32 = "assister",
+        assisted_flash: bool = "assistedflash",
+        weapon: String = "weapon",
+        headshot: bool = "headshot",
+        penetrated: i32 = "penetrated",
+        no_scope: bool = "noscope",
+        through_smoke: bool = "thrusmoke",
+        attacker_blind: bool = "attackerblind",
+        distance: f32 = "distance"
+    };
+    "player_hurt" => PlayerHurt {
+        user_id: i32 = "userid",
+        attacker: i32 = "attacker",
+        health: i32 = "health",
+        armor: i32 = "armor",
+        weapon: String = "weapon",
+        dmg_health: i32 = "dmg_health",
+        dmg_armor: i32 = "dmg_armor",
+        hitgroup: i32 = "hitgroup"
+    };
+    "player_blind" => PlayerBlind {
+        user_id: i32 = "userid",
+        attacker: i32 = "attacker",
+        entity_id: i32 = "entityid",
+        blind_duration: f32 = "blind_duration"
+    };
+    "player_spawn" => PlayerSpawn {
+        user_id: i32 = "userid",
+        team: i32 = "teamnum"
+    };
+    "player_team" => PlayerTeam {
+        user_id: i32 = "userid",
+        team: i32 = "team",
+        old_team: i32 = "oldteam",
+        disconnect: bool = "disconnect",
+        is_bot: bool = "isbot",
+        silent: bool = "silent"
+    };
+    "player_disconnect" => PlayerDisconnect {
+        user_id: i32 = "userid",
+        reason: String = "reason",
+        name: String = "name",
+        network_id: String = "networkid"
+    };
+    "weapon_fire" => WeaponFire {
+        user_id: i32 = "userid",
+        weapon: String = "weapon",
+        silenced: bool = "silenced"
+    };
+    "item_purchase" => ItemPurchase {
+        user_id: i32 = "userid",
+        team: i32 = "team",
+        weapon: String = "weapon"
+    };
+    "round_start" => RoundStart {
+        time_limit: i32 = "timelimit",
+        frag_limit: i32 = "fraglimit",
+        objective: String = "objective"
+    };
+    "round_freeze_end" => RoundFreezeEnd {};
+    "round_end" => RoundEnd {
+        winner: i32 = "winner",
+        reason: i32 = "reason",
+        message: String = "message",
+        player_count: i32 = "player_count"
+    };
+    "round_mvp" => RoundMvp {
+        user_id: i32 = "userid",
+        reason: i32 = "reason"
+    };
+    "bomb_planted" => BombPlanted {
+        user_id: i32 = "userid",
+        site: i32 = "site"
+    };
+    "bomb_defused" => BombDefused {
+        user_id: i32 = "userid",
+        site: i32 = "site"
+    };
+    "bomb_exploded" => BombExploded {
+        user_id: i32 = "userid",
+        site: i32 = "site"
+    };
+    "hegrenade_detonate" => HeGrenadeDetonate {
+        user_id: i32 = "userid",
+        entity_id: i32 = "entityid",
+        x: f32 = "x",
+        y: f32 = "y",
+        z: f32 = "z"
+    };
+    "flashbang_detonate" => FlashbangDetonate {
+        user_id: i32 = "userid",
+        entity_id: i32 = "entityid",
+        x: f32 = "x",
+        y: f32 = "y",
+        z: f32 = "z"
+    };
+    "smokegrenade_detonate" => SmokeGrenadeDetonate {
+        user_id: i32 = "userid",
+        entity_id: i32 = "entityid",
+        x: f32 = "x",
+        y: f32 = "y",
+        z: f32 = "z"
+    }
+}
+
+pub trait GameEventHandler: EventHandler {
+    on_fn! {
+        on_decoded_game_event => GameEvent;
+
+        on_player_death => PlayerDeath;
+        on_player_hurt => PlayerHurt;
+        on_player_blind => PlayerBlind;
+        on_player_spawn => PlayerSpawn;
+        on_player_team => PlayerTeam;
+        on_player_disconnect => PlayerDisconnect;
+        on_weapon_fire => WeaponFire;
+        on_item_purchase => ItemPurchase;
+        on_round_start => RoundStart;
+        on_round_freeze_end => RoundFreezeEnd;
+        on_round_end => RoundEnd;
+        on_round_mvp => RoundMvp;
+        on_bomb_planted => BombPlanted;
+        on_bomb_defused => BombDefused;
+        on_bomb_exploded => BombExploded;
+        on_hegrenade_detonate => HeGrenadeDetonate;
+        on_flashbang_detonate => FlashbangDetonate;
+        on_smokegrenade_detonate => SmokeGrenadeDetonate
+    }
+}
+
+// Events whose id has no descriptor (yet) fall through to on_game_event.
+pub struct GameEventDecoder<T>(pub T, HashMap<i32, CSVCMsg_GameEventList_descriptor_t>);
+
+impl<T> GameEventDecoder<T> {
+    pub fn new(handler: T) -> Self {
+        GameEventDecoder(handler, HashMap::new())
+    }
+}
+
+impl<T: GameEventHandler> EventHandler for GameEventDecoder<T> {
+    fn on_game_event_list(&mut self, event: &CSVCMsg_GameEventList, context: &ParserContext) -> Result<Control, Error> {
+        self.1 = event.get_descriptors().iter()
+            .map(|descriptor| (descriptor.get_eventid(), descriptor.clone()))
+            .collect();
+
+        self.0.on_game_event_list(event, context)
+    }
+
+    fn on_game_event(&mut self, event: &CSVCMsg_GameEvent, context: &ParserContext) -> Result<Control, Error> {
+        let game_event = match self.1.get(&event.get_eventid()) {
+            Some(descriptor) if !self.0.wants(&MessageKind::GameEvent(descriptor.get_name())) => return Ok(Control::Continue),
+            Some(descriptor) => GameEvent::decode(descriptor, event),
+            None => return self.0.on_game_event(event, context)
+        };
+
+        let control = self.0.on_decoded_game_event(&game_event, context)?;
+        Ok(control.max(dispatch_typed(&mut self.0, &game_event, context)?))
+    }
+
+    // The typed events can only be decoded from svc_GameEvent with the descriptors of
+    // svc_GameEventList, so wanting one of them is enough.
+    fn wants(&self, message: &MessageKind<'_>) -> bool {
+        match message {
+            MessageKind::Svc(SVC_Messages::svc_GameEventList) | MessageKind::Svc(SVC_Messages::svc_GameEvent)
+                if TYPED_GAME_EVENTS.iter().any(|name| self.0.wants(&MessageKind::GameEvent(name))) => true,
+            message => self.0.wants(message)
+        }
+    }
+
+    forward_to_inner! {
+        on_dem_header => DemHeader;
+        on_packet_info => PacketInfo;
+        on_server_class => ServerClass;
+        on_server_class_registry => ServerClassRegistry;
+        on_string_tables => StringTablesSnapshot;
+        on_console_cmd => ConsoleCommand;
+        on_user_cmd => UserCmd;
+        on_custom_data => CustomData;
+        on_truncated => TruncatedDemo;
+        on_roster_change => RosterChange<'_>;
+        on_string_table_entry => StringTableEntryUpdate<'_>;
+        on_entity_update => EntityUpdate<'_>;
+        on_tick_start => TickStart;
+        on_tick_end => TickEnd<'_>;
+
+        on_nop => CNETMsg_NOP;
+        on_disconnect => CNETMsg_Disconnect;
+        on_file => CNETMsg_File;
+        on_split_screen_user => CNETMsg_SplitScreenUser;
+        on_tick => CNETMsg_Tick;
+        on_string_cmd => CNETMsg_StringCmd;
+        on_set_con_var => CNETMsg_SetConVar;
+        on_signon_state => CNETMsg_SignonState;
+        on_player_avatar_data => CNETMsg_PlayerAvatarData;
+
+        on_server_info => CSVCMsg_ServerInfo;
+        on_send_table => CSVCMsg_SendTable;
+        on_class_info => CSVCMsg_ClassInfo;
+        on_set_pause => CSVCMsg_SetPause;
+        on_create_string_table => CSVCMsg_CreateStringTable;
+        on_update_string_table => CSVCMsg_UpdateStringTable;
+        on_voice_init => CSVCMsg_VoiceInit;
+        on_voice_data => CSVCMsg_VoiceData;
+        on_print => CSVCMsg_Print;
+        on_sounds => CSVCMsg_Sounds;
+        on_set_view => CSVCMsg_SetView;
+        on_fix_angle => CSVCMsg_FixAngle;
+        on_crosshair_angle => CSVCMsg_CrosshairAngle;
+        on_bspdecal => CSVCMsg_BSPDecal;
+        on_split_screen => CSVCMsg_SplitScreen;
+        on_user_message => CSVCMsg_UserMessage;
+        on_entity_message => CSVCMsg_EntityMsg;
+        on_packet_entities => CSVCMsg_PacketEntities;
+        on_temp_entities => CSVCMsg_TempEntities;
+        on_prefetch => CSVCMsg_Prefetch;
+        on_menu => CSVCMsg_Menu;
+        on_get_cvar_value => CSVCMsg_GetCvarValue;
+        on_paintmap_data => CSVCMsg_PaintmapData;
+        on_cmd_key_values => CSVCMsg_CmdKeyValues;
+        on_encrypted_data => CSVCMsg_EncryptedData;
+        on_hltv_replay => CSVCMsg_HltvReplay;
+        on_broadcast_command => CSVCMsg_Broadcast_Command
+    }
+}
+
+// Lets a GameEventDecoder wrap a UserMessageDecoder so both can be used at once.
+impl<T: GameEventHandler + UserMessageEventHandler> GameEventHandler for UserMessageDecoder<T> {
+    forward_to_inner! {
+        on_decoded_game_event => GameEvent;
+
+        on_player_death => PlayerDeath;
+        on_player_hurt => PlayerHurt;
+        on_player_blind => PlayerBlind;
+        on_player_spawn => PlayerSpawn;
+        on_player_team => PlayerTeam;
+        on_player_disconnect => PlayerDisconnect;
+        on_weapon_fire => WeaponFire;
+        on_item_purchase => ItemPurchase;
+        on_round_start => RoundStart;
+        on_round_freeze_end => RoundFreezeEnd;
+        on_round_end => RoundEnd;
+        on_round_mvp => RoundMvp;
+        on_bomb_planted => BombPlanted;
+        on_bomb_defused => BombDefused;
+        on_bomb_exploded => BombExploded;
+        on_hegrenade_detonate => HeGrenadeDetonate;
+        on_flashbang_detonate => FlashbangDetonate;
+        on_smokegrenade_detonate => SmokeGrenadeDetonate
+    }
+}
+
+impl_dispatch! {
+    GameEventHandler;
+
+    on_decoded_game_event => GameEvent;
+
+    on_player_death => PlayerDeath;
+    on_player_hurt => PlayerHurt;
+    on_player_blind => PlayerBlind;
+    on_player_spawn => PlayerSpawn;
+    on_player_team => PlayerTeam;
+    on_player_disconnect => PlayerDisconnect;
+    on_weapon_fire => WeaponFire;
+    on_item_purchase => ItemPurchase;
+    on_round_start => RoundStart;
+    on_round_freeze_end => RoundFreezeEnd;
+    on_round_end => RoundEnd;
+    on_round_mvp => RoundMvp;
+    on_bomb_planted => BombPlanted;
+    on_bomb_defused => BombDefused;
+    on_bomb_exploded => BombExploded;
+    on_hegrenade_detonate => HeGrenadeDetonate;
+    on_flashbang_detonate => FlashbangDetonate;
+    on_smokegrenade_detonate => SmokeGrenadeDetonate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wants nothing but the game events it lists.
+    struct Wanting(&'static [&'static str]);
+
+    impl EventHandler for Wanting {
+        fn wants(&self, message: &MessageKind<'_>) -> bool {
+            matches!(message, MessageKind::GameEvent(name) if self.0.contains(name))
+        }
+    }
+
+    impl GameEventHandler for Wanting {}
+
+    #[derive(Default)]
+    struct Deaths(Vec<PlayerDeath>);
+
+    impl EventHandler for Deaths {}
+
+    impl GameEventHandler for Deaths {
+        fn on_player_death(&mut self, event: &PlayerDeath, _: &ParserContext) -> Result<Control, Error> {
+            self.0.push(event.clone());
+            Ok(Control::Continue)
+        }
+    }
+
+    fn player_death(fields: Vec<(&str, GameEventValue)>) -> GameEvent {
+        GameEvent {
+            event_id: 0,
+            name: "player_death".to_owned(),
+            fields: fields.into_iter().map(|(key, value)| (key.to_owned(), value)).collect()
+        }
+    }
+
+    #[test]
+    fn wants_the_game_event_messages_for_a_wanted_typed_event() {
+        let decoder = GameEventDecoder::new(Wanting(&["player_death"]));
+        assert!(decoder.wants(&MessageKind::Svc(SVC_Messages::svc_GameEventList)));
+        assert!(decoder.wants(&MessageKind::Svc(SVC_Messages::svc_GameEvent)));
+        assert!(!decoder.wants(&MessageKind::Svc(SVC_Messages::svc_UserMessage)));
+
+        let decoder = GameEventDecoder::new(Wanting(&["player_footstep"]));
+        assert!(!decoder.wants(&MessageKind::Svc(SVC_Messages::svc_GameEventList)));
+        assert!(!decoder.wants(&MessageKind::Svc(SVC_Messages::svc_GameEvent)));
+    }
+
+    #[test]
+    fn typed_events_use_the_game_event_coercions() {
+        let mut handler = Deaths::default();
+        let event = player_death(vec![("userid", GameEventValue::Short(4)), ("weapon", GameEventValue::String("ak47".to_owned()))]);
+        dispatch_typed(&mut handler, &event, &ParserContext::default()).unwrap();
+
+        assert_eq!(handler.0.len(), 1);
+        assert_eq!((handler.0[0].user_id, handler.0[0].weapon.as_str(), handler.0[0].attacker), (4, "ak47", 0));
+
+        // Rejected like it is for #[demo_handler] methods taking a &PlayerDeath.
+        let event = player_death(vec![("headshot", GameEventValue::String("maybe".to_owned()))]);
+        assert!(dispatch_typed(&mut handler, &event, &ParserContext::default()).is_err());
+        assert!(PlayerDeath::from_game_event(&event).is_err());
+        assert_eq!(handler.0.len(), 1);
+    }
+}
@@ -1,19 +1,27 @@
+use std::collections::HashMap;
 
 use protobuf::Message;
 use protobuf::ProtobufEnum;
 
 pub use super::format::*;
+pub use super::sendtables::ServerClassRegistry;
+pub use super::stringtables::StringTableEntryUpdate;
+pub use super::players::RosterChange;
+pub use super::entities::EntityUpdate;
 pub use super::protos::netmessages::*;
 pub use super::protos::cstrike15_usermessages::*;
 
+use crate::entities::EntityTable;
+use crate::players::PlayerRoster;
+use crate::stringtables::StringTables;
 use crate::Error;
 
 macro_rules! impl_dispatch {
     ($handler:path; $($ident:ident => $ty:ty);+) => ($(
         impl<E: $handler> Dispatcher<$ty> for E {
             #[inline(always)]
-            fn dispatch(&self, event: &$ty) -> Result<(), Error> {
-                self.$ident(event)
+            fn dispatch(&mut self, event: &$ty, context: &ParserContext) -> Result<Control, Error> {
+                self.$ident(event, context)
             }
         }
     )+);
@@ -22,29 +30,79 @@ macro_rules! impl_dispatch {
 macro_rules! on_fn {
     ($($ident:ident => $ty:ty);+) => ($(
         #[inline]
-        fn $ident(&self, _: &$ty) -> Result<(), Error> { Ok(()) }
+        fn $ident(&mut self, _: &$ty, _: &ParserContext) -> Result<Control, Error> { Ok(Control::Continue) }
     )+);
 }
 
 macro_rules! forward_to_inner {
     ($($ident:ident => $ty:ty);+) => ($(
-        fn $ident(&self, event: &$ty) -> Result<(), Error> {
-            self.0.$ident(event)
+        fn $ident(&mut self, event: &$ty, context: &ParserContext) -> Result<Control, Error> {
+            self.0.$ident(event, context)
         }
     )+);
 }
 
 macro_rules! parse_and_dispatch {
-    ($ident:ident, $data:ident, $dispatcher:expr) => {
+    ($ident:ident, $data:ident, $dispatcher:expr, $context:ident) => {
         {
             let event = $ident::parse_from_bytes($data)?;
-            $dispatcher.dispatch(&event)?;
+            $dispatcher.dispatch(&event, $context)?
         }
     };
 }
 
+// Returned by every handler. Control only takes effect once the message that caused
+// it has been applied, so derived events (string table entries, roster changes and
+// entity updates) of that message are still delivered.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Control {
+    #[default]
+    Continue,
+    // Stop dispatching the remaining messages of the current dem_packet. They are still
+    // applied to the entity table and string tables.
+    SkipPacket,
+    Stop
+}
+
+// Where the parser is in the demo, passed along with every event.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ParserContext {
+    // Tick from the command header of the current frame.
+    pub tick: i32,
+    // Last tick announced by net_Tick, which is what game events and entities belong to.
+    pub server_tick: i32,
+    // Index of the current frame, counting from zero.
+    pub frame: u32,
+    pub player_slot: u8,
+    // Seconds per tick from svc_ServerInfo, zero until it has been received.
+    pub tick_interval: f32
+}
+
+impl ParserContext {
+    // Seconds since the start of the demo, based on the demo tick.
+    pub fn time(&self) -> f32 {
+        self.tick as f32 * self.tick_interval
+    }
+
+    pub fn server_time(&self) -> f32 {
+        self.server_tick as f32 * self.tick_interval
+    }
+}
+
+// What EventHandler::wants is asked about before a message is decoded. The decoders
+// only see the messages they unwrap, so a handler that wants CS_UM_SayText2 has to
+// want svc_UserMessage as well. GameEventDecoder asks for svc_GameEventList and
+// svc_GameEvent itself whenever one of its typed events is wanted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageKind<'a> {
+    Net(NET_Messages),
+    Svc(SVC_Messages),
+    UserMessage(ECstrike15UserMessages),
+    GameEvent(&'a str)
+}
+
 pub trait Dispatcher<E> {
-    fn dispatch(&self, event: &E) -> Result<(), Error>;
+    fn dispatch(&mut self, event: &E, context: &ParserContext) -> Result<Control, Error>;
 }
 
 pub trait EventHandler {
@@ -52,6 +110,17 @@ pub trait EventHandler {
         on_dem_header => DemHeader;
         on_packet_info => PacketInfo;
         on_server_class => ServerClass;
+        on_server_class_registry => ServerClassRegistry;
+        on_string_tables => StringTablesSnapshot;
+        on_console_cmd => ConsoleCommand;
+        on_user_cmd => UserCmd;
+        on_custom_data => CustomData;
+        on_truncated => TruncatedDemo;
+        on_roster_change => RosterChange<'_>;
+        on_string_table_entry => StringTableEntryUpdate<'_>;
+        on_entity_update => EntityUpdate<'_>;
+        on_tick_start => TickStart;
+        on_tick_end => TickEnd<'_>;
 
         on_nop => CNETMsg_NOP;
         on_disconnect => CNETMsg_Disconnect;
@@ -93,6 +162,13 @@ pub trait EventHandler {
         on_hltv_replay => CSVCMsg_HltvReplay;
         on_broadcast_command => CSVCMsg_Broadcast_Command
     }
+
+    // Messages a handler does not want are skipped without being decoded, unless the
+    // parser needs them to keep its string tables or entities up to date.
+    #[inline]
+    fn wants(&self, _: &MessageKind<'_>) -> bool {
+        true
+    }
 }
 
 pub trait UserMessageEventHandler: EventHandler {
@@ -123,7 +199,7 @@ pub trait UserMessageEventHandler: EventHandler {
         on_process_spotted_entity_update => CCSUsrMsg_ProcessSpottedEntityUpdate;
         on_reload_effect => CCSUsrMsg_ReloadEffect;
         on_adjust_money => CCSUsrMsg_AdjustMoney;
-        //on_UpdateTeamMoney => CCSUsrMsg_UpdateTeamMoney;
+        on_update_team_money => UpdateTeamMoney<'_>;
         on_stop_spectator_mode => CCSUsrMsg_StopSpectatorMode;
         on_kill_cam => CCSUsrMsg_KillCam;
         on_desired_timescale => CCSUsrMsg_DesiredTimescale;
@@ -166,110 +242,248 @@ pub trait UserMessageEventHandler: EventHandler {
         on_entity_outline_highlight => CCSUsrMsg_EntityOutlineHighlight;
         on_ssui => CCSUsrMsg_SSUI;
         on_survival_stats => CCSUsrMsg_SurvivalStats;
-        //on_DisconnectToLobby2 => CCSUsrMsg_DisconnectToLobby2;
+        on_disconnect_to_lobby2 => CCSUsrMsg_DisconnectToLobby;
         on_end_of_match_all_players_data => CCSUsrMsg_EndOfMatchAllPlayersData;
         on_round_impact_score_data => CCSUsrMsg_RoundImpactScoreData;
         on_current_round_odds => CCSUsrMsg_CurrentRoundOdds;
-        on_deep_stats => CCSUsrMsg_DeepStats
+        on_deep_stats => CCSUsrMsg_DeepStats;
+
+        on_unknown_user_message => UnknownUserMessage<'_>
     }
 }
 
+// The protos define no payload for CS_UM_UpdateTeamMoney, so its bytes are passed on as is.
+#[derive(Copy, Clone, Debug)]
+pub struct UpdateTeamMoney<'a> {
+    pub data: &'a [u8]
+}
+
+// Fired before the first frame of a tick is parsed.
+#[derive(Copy, Clone, Debug)]
+pub struct TickStart {
+    pub tick: i32
+}
+
+// Fired once every frame of a tick has been parsed, so all string table, entity and
+// game event updates of the tick have been applied and the state below is consistent.
+// A demo that ends without dem_stop or is stopped by a handler has no final TickEnd.
+#[derive(Copy, Clone, Debug)]
+pub struct TickEnd<'a> {
+    pub tick: i32,
+    pub server_classes: Option<&'a ServerClassRegistry>,
+    pub string_tables: &'a StringTables,
+    pub players: &'a PlayerRoster,
+    pub entities: &'a EntityTable
+}
+
+// User messages with an id ECstrike15UserMessages does not know about, e.g. from newer game builds.
+#[derive(Copy, Clone, Debug)]
+pub struct UnknownUserMessage<'a> {
+    pub msg_type: i32,
+    pub data: &'a [u8]
+}
+
 pub struct UserMessageDecoder<T>(pub T);
 
 impl<T: UserMessageEventHandler> EventHandler for UserMessageDecoder<T> {
-    fn on_user_message(&self, event: &CSVCMsg_UserMessage) -> Result<(), Error> {
+    fn on_user_message(&mut self, event: &CSVCMsg_UserMessage, context: &ParserContext) -> Result<Control, Error> {
         let command = event.get_msg_type();
         let data = event.get_msg_data();
 
-        if let Some(command) = ECstrike15UserMessages::from_i32(command) {
+        let control = if let Some(command) = ECstrike15UserMessages::from_i32(command) {
+            if !self.0.wants(&MessageKind::UserMessage(command)) {
+                return Ok(Control::Continue);
+            }
+
             use ECstrike15UserMessages::*;
 
             match command {
-                CS_UM_VGUIMenu => parse_and_dispatch!(CCSUsrMsg_VGUIMenu, data, self.0),
-                CS_UM_Geiger => parse_and_dispatch!(CCSUsrMsg_Geiger, data, self.0),
-                CS_UM_Train => parse_and_dispatch!(CCSUsrMsg_Train, data, self.0),
-                CS_UM_HudText => parse_and_dispatch!(CCSUsrMsg_HudText, data, self.0),
-                CS_UM_SayText => parse_and_dispatch!(CCSUsrMsg_SayText, data, self.0),
-                CS_UM_SayText2 => parse_and_dispatch!(CCSUsrMsg_SayText2, data, self.0),
-                CS_UM_TextMsg => parse_and_dispatch!(CCSUsrMsg_TextMsg, data, self.0),
-                CS_UM_HudMsg => parse_and_dispatch!(CCSUsrMsg_HudMsg, data, self.0),
-                CS_UM_ResetHud => parse_and_dispatch!(CCSUsrMsg_ResetHud, data, self.0),
-                CS_UM_GameTitle => parse_and_dispatch!(CCSUsrMsg_GameTitle, data, self.0),
-                CS_UM_Shake => parse_and_dispatch!(CCSUsrMsg_Shake, data, self.0),
-                CS_UM_Fade => parse_and_dispatch!(CCSUsrMsg_Fade, data, self.0),
-                CS_UM_Rumble => parse_and_dispatch!(CCSUsrMsg_Rumble, data, self.0),
-                CS_UM_CloseCaption => parse_and_dispatch!(CCSUsrMsg_CloseCaption, data, self.0),
-                CS_UM_CloseCaptionDirect => parse_and_dispatch!(CCSUsrMsg_CloseCaptionDirect, data, self.0),
-                CS_UM_SendAudio => parse_and_dispatch!(CCSUsrMsg_SendAudio, data, self.0),
-                CS_UM_RawAudio => parse_and_dispatch!(CCSUsrMsg_RawAudio, data, self.0),
-                CS_UM_VoiceMask => parse_and_dispatch!(CCSUsrMsg_VoiceMask, data, self.0),
-                CS_UM_RequestState => parse_and_dispatch!(CCSUsrMsg_RequestState, data, self.0),
-                CS_UM_Damage => parse_and_dispatch!(CCSUsrMsg_Damage, data, self.0),
-                CS_UM_RadioText => parse_and_dispatch!(CCSUsrMsg_RadioText, data, self.0),
-                CS_UM_HintText => parse_and_dispatch!(CCSUsrMsg_HintText, data, self.0),
-                CS_UM_KeyHintText => parse_and_dispatch!(CCSUsrMsg_KeyHintText, data, self.0),
-                CS_UM_ProcessSpottedEntityUpdate => parse_and_dispatch!(CCSUsrMsg_ProcessSpottedEntityUpdate, data, self.0),
-                CS_UM_ReloadEffect => parse_and_dispatch!(CCSUsrMsg_ReloadEffect, data, self.0),
-                CS_UM_AdjustMoney => parse_and_dispatch!(CCSUsrMsg_AdjustMoney, data, self.0),
-                CS_UM_UpdateTeamMoney => todo!(),
-                CS_UM_StopSpectatorMode => parse_and_dispatch!(CCSUsrMsg_StopSpectatorMode, data, self.0),
-                CS_UM_KillCam => parse_and_dispatch!(CCSUsrMsg_KillCam, data, self.0),
-                CS_UM_DesiredTimescale => parse_and_dispatch!(CCSUsrMsg_DesiredTimescale, data, self.0),
-                CS_UM_CurrentTimescale => parse_and_dispatch!(CCSUsrMsg_CurrentTimescale, data, self.0),
-                CS_UM_AchievementEvent => parse_and_dispatch!(CCSUsrMsg_AchievementEvent, data, self.0),
-                CS_UM_MatchEndConditions => parse_and_dispatch!(CCSUsrMsg_MatchEndConditions, data, self.0),
-                CS_UM_DisconnectToLobby => parse_and_dispatch!(CCSUsrMsg_DisconnectToLobby, data, self.0),
-                CS_UM_PlayerStatsUpdate => parse_and_dispatch!(CCSUsrMsg_PlayerStatsUpdate, data, self.0),
-                CS_UM_DisplayInventory => parse_and_dispatch!(CCSUsrMsg_DisplayInventory, data, self.0),
-                CS_UM_WarmupHasEnded => parse_and_dispatch!(CCSUsrMsg_WarmupHasEnded, data, self.0),
-                CS_UM_ClientInfo => parse_and_dispatch!(CCSUsrMsg_ClientInfo, data, self.0),
-                CS_UM_XRankGet => parse_and_dispatch!(CCSUsrMsg_XRankGet, data, self.0),
-                CS_UM_XRankUpd => parse_and_dispatch!(CCSUsrMsg_XRankUpd, data, self.0),
-                CS_UM_CallVoteFailed => parse_and_dispatch!(CCSUsrMsg_CallVoteFailed, data, self.0),
-                CS_UM_VoteStart => parse_and_dispatch!(CCSUsrMsg_VoteStart, data, self.0),
-                CS_UM_VotePass => parse_and_dispatch!(CCSUsrMsg_VotePass, data, self.0),
-                CS_UM_VoteFailed => parse_and_dispatch!(CCSUsrMsg_VoteFailed, data, self.0),
-                CS_UM_VoteSetup => parse_and_dispatch!(CCSUsrMsg_VoteSetup, data, self.0),
-                CS_UM_ServerRankRevealAll => parse_and_dispatch!(CCSUsrMsg_ServerRankRevealAll, data, self.0),
-                CS_UM_SendLastKillerDamageToClient => parse_and_dispatch!(CCSUsrMsg_SendLastKillerDamageToClient, data, self.0),
-                CS_UM_ServerRankUpdate => parse_and_dispatch!(CCSUsrMsg_ServerRankUpdate, data, self.0),
-                CS_UM_ItemPickup => parse_and_dispatch!(CCSUsrMsg_ItemPickup, data, self.0),
-                CS_UM_ShowMenu => parse_and_dispatch!(CCSUsrMsg_ShowMenu, data, self.0),
-                CS_UM_BarTime => parse_and_dispatch!(CCSUsrMsg_BarTime, data, self.0),
-                CS_UM_AmmoDenied => parse_and_dispatch!(CCSUsrMsg_AmmoDenied, data, self.0),
-                CS_UM_MarkAchievement => parse_and_dispatch!(CCSUsrMsg_MarkAchievement, data, self.0),
-                CS_UM_MatchStatsUpdate => parse_and_dispatch!(CCSUsrMsg_MatchStatsUpdate, data, self.0),
-                CS_UM_ItemDrop => parse_and_dispatch!(CCSUsrMsg_ItemDrop, data, self.0),
-                CS_UM_GlowPropTurnOff => parse_and_dispatch!(CCSUsrMsg_GlowPropTurnOff, data, self.0),
-                CS_UM_SendPlayerItemDrops => parse_and_dispatch!(CCSUsrMsg_SendPlayerItemDrops, data, self.0),
-                CS_UM_RoundBackupFilenames => parse_and_dispatch!(CCSUsrMsg_RoundBackupFilenames, data, self.0),
-                CS_UM_SendPlayerItemFound => parse_and_dispatch!(CCSUsrMsg_SendPlayerItemFound, data, self.0),
-                CS_UM_ReportHit => parse_and_dispatch!(CCSUsrMsg_ReportHit, data, self.0),
-                CS_UM_XpUpdate => parse_and_dispatch!(CCSUsrMsg_XpUpdate, data, self.0),
-                CS_UM_QuestProgress => parse_and_dispatch!(CCSUsrMsg_QuestProgress, data, self.0),
-                CS_UM_ScoreLeaderboardData => parse_and_dispatch!(CCSUsrMsg_ScoreLeaderboardData, data, self.0),
-                CS_UM_PlayerDecalDigitalSignature => parse_and_dispatch!(CCSUsrMsg_PlayerDecalDigitalSignature, data, self.0),
-                CS_UM_WeaponSound => parse_and_dispatch!(CCSUsrMsg_WeaponSound, data, self.0),
-                CS_UM_UpdateScreenHealthBar => parse_and_dispatch!(CCSUsrMsg_UpdateScreenHealthBar, data, self.0),
-                CS_UM_EntityOutlineHighlight => parse_and_dispatch!(CCSUsrMsg_EntityOutlineHighlight, data, self.0),
-                CS_UM_SSUI => parse_and_dispatch!(CCSUsrMsg_SSUI, data, self.0),
-                CS_UM_SurvivalStats => parse_and_dispatch!(CCSUsrMsg_SurvivalStats, data, self.0),
-                CS_UM_DisconnectToLobby2 => todo!(),
-                CS_UM_EndOfMatchAllPlayersData => parse_and_dispatch!(CCSUsrMsg_EndOfMatchAllPlayersData, data, self.0),
-                CS_UM_RoundImpactScoreData => parse_and_dispatch!(CCSUsrMsg_RoundImpactScoreData, data, self.0),
-                CS_UM_CurrentRoundOdds => parse_and_dispatch!(CCSUsrMsg_CurrentRoundOdds, data, self.0),
-                CS_UM_DeepStats => parse_and_dispatch!(CCSUsrMsg_DeepStats, data, self.0)
+                CS_UM_VGUIMenu => parse_and_dispatch!(CCSUsrMsg_VGUIMenu, data, self.0, context),
+                CS_UM_Geiger => parse_and_dispatch!(CCSUsrMsg_Geiger, data, self.0, context),
+                CS_UM_Train => parse_and_dispatch!(CCSUsrMsg_Train, data, self.0, context),
+                CS_UM_HudText => parse_and_dispatch!(CCSUsrMsg_HudText, data, self.0, context),
+                CS_UM_SayText => parse_and_dispatch!(CCSUsrMsg_SayText, data, self.0, context),
+                CS_UM_SayText2 => parse_and_dispatch!(CCSUsrMsg_SayText2, data, self.0, context),
+                CS_UM_TextMsg => parse_and_dispatch!(CCSUsrMsg_TextMsg, data, self.0, context),
+                CS_UM_HudMsg => parse_and_dispatch!(CCSUsrMsg_HudMsg, data, self.0, context),
+                CS_UM_ResetHud => parse_and_dispatch!(CCSUsrMsg_ResetHud, data, self.0, context),
+                CS_UM_GameTitle => parse_and_dispatch!(CCSUsrMsg_GameTitle, data, self.0, context),
+                CS_UM_Shake => parse_and_dispatch!(CCSUsrMsg_Shake, data, self.0, context),
+                CS_UM_Fade => parse_and_dispatch!(CCSUsrMsg_Fade, data, self.0, context),
+                CS_UM_Rumble => parse_and_dispatch!(CCSUsrMsg_Rumble, data, self.0, context),
+                CS_UM_CloseCaption => parse_and_dispatch!(CCSUsrMsg_CloseCaption, data, self.0, context),
+                CS_UM_CloseCaptionDirect => parse_and_dispatch!(CCSUsrMsg_CloseCaptionDirect, data, self.0, context),
+                CS_UM_SendAudio => parse_and_dispatch!(CCSUsrMsg_SendAudio, data, self.0, context),
+                CS_UM_RawAudio => parse_and_dispatch!(CCSUsrMsg_RawAudio, data, self.0, context),
+                CS_UM_VoiceMask => parse_and_dispatch!(CCSUsrMsg_VoiceMask, data, self.0, context),
+                CS_UM_RequestState => parse_and_dispatch!(CCSUsrMsg_RequestState, data, self.0, context),
+                CS_UM_Damage => parse_and_dispatch!(CCSUsrMsg_Damage, data, self.0, context),
+                CS_UM_RadioText => parse_and_dispatch!(CCSUsrMsg_RadioText, data, self.0, context),
+                CS_UM_HintText => parse_and_dispatch!(CCSUsrMsg_HintText, data, self.0, context),
+                CS_UM_KeyHintText => parse_and_dispatch!(CCSUsrMsg_KeyHintText, data, self.0, context),
+                CS_UM_ProcessSpottedEntityUpdate => parse_and_dispatch!(CCSUsrMsg_ProcessSpottedEntityUpdate, data, self.0, context),
+                CS_UM_ReloadEffect => parse_and_dispatch!(CCSUsrMsg_ReloadEffect, data, self.0, context),
+                CS_UM_AdjustMoney => parse_and_dispatch!(CCSUsrMsg_AdjustMoney, data, self.0, context),
+                CS_UM_UpdateTeamMoney => self.0.dispatch(&UpdateTeamMoney { data }, context)?,
+                CS_UM_StopSpectatorMode => parse_and_dispatch!(CCSUsrMsg_StopSpectatorMode, data, self.0, context),
+                CS_UM_KillCam => parse_and_dispatch!(CCSUsrMsg_KillCam, data, self.0, context),
+                CS_UM_DesiredTimescale => parse_and_dispatch!(CCSUsrMsg_DesiredTimescale, data, self.0, context),
+                CS_UM_CurrentTimescale => parse_and_dispatch!(CCSUsrMsg_CurrentTimescale, data, self.0, context),
+                CS_UM_AchievementEvent => parse_and_dispatch!(CCSUsrMsg_AchievementEvent, data, self.0, context),
+                CS_UM_MatchEndConditions => parse_and_dispatch!(CCSUsrMsg_MatchEndConditions, data, self.0, context),
+                CS_UM_DisconnectToLobby => parse_and_dispatch!(CCSUsrMsg_DisconnectToLobby, data, self.0, context),
+                CS_UM_PlayerStatsUpdate => parse_and_dispatch!(CCSUsrMsg_PlayerStatsUpdate, data, self.0, context),
+                CS_UM_DisplayInventory => parse_and_dispatch!(CCSUsrMsg_DisplayInventory, data, self.0, context),
+                CS_UM_WarmupHasEnded => parse_and_dispatch!(CCSUsrMsg_WarmupHasEnded, data, self.0, context),
+                CS_UM_ClientInfo => parse_and_dispatch!(CCSUsrMsg_ClientInfo, data, self.0, context),
+                CS_UM_XRankGet => parse_and_dispatch!(CCSUsrMsg_XRankGet, data, self.0, context),
+                CS_UM_XRankUpd => parse_and_dispatch!(CCSUsrMsg_XRankUpd, data, self.0, context),
+                CS_UM_CallVoteFailed => parse_and_dispatch!(CCSUsrMsg_CallVoteFailed, data, self.0, context),
+                CS_UM_VoteStart => parse_and_dispatch!(CCSUsrMsg_VoteStart, data, self.0, context),
+                CS_UM_VotePass => parse_and_dispatch!(CCSUsrMsg_VotePass, data, self.0, context),
+                CS_UM_VoteFailed => parse_and_dispatch!(CCSUsrMsg_VoteFailed, data, self.0, context),
+                CS_UM_VoteSetup => parse_and_dispatch!(CCSUsrMsg_VoteSetup, data, self.0, context),
+                CS_UM_ServerRankRevealAll => parse_and_dispatch!(CCSUsrMsg_ServerRankRevealAll, data, self.0, context),
+                CS_UM_SendLastKillerDamageToClient => parse_and_dispatch!(CCSUsrMsg_SendLastKillerDamageToClient, data, self.0, context),
+                CS_UM_ServerRankUpdate => parse_and_dispatch!(CCSUsrMsg_ServerRankUpdate, data, self.0, context),
+                CS_UM_ItemPickup => parse_and_dispatch!(CCSUsrMsg_ItemPickup, data, self.0, context),
+                CS_UM_ShowMenu => parse_and_dispatch!(CCSUsrMsg_ShowMenu, data, self.0, context),
+                CS_UM_BarTime => parse_and_dispatch!(CCSUsrMsg_BarTime, data, self.0, context),
+                CS_UM_AmmoDenied => parse_and_dispatch!(CCSUsrMsg_AmmoDenied, data, self.0, context),
+                CS_UM_MarkAchievement => parse_and_dispatch!(CCSUsrMsg_MarkAchievement, data, self.0, context),
+                CS_UM_MatchStatsUpdate => parse_and_dispatch!(CCSUsrMsg_MatchStatsUpdate, data, self.0, context),
+                CS_UM_ItemDrop => parse_and_dispatch!(CCSUsrMsg_ItemDrop, data, self.0, context),
+                CS_UM_GlowPropTurnOff => parse_and_dispatch!(CCSUsrMsg_GlowPropTurnOff, data, self.0, context),
+                CS_UM_SendPlayerItemDrops => parse_and_dispatch!(CCSUsrMsg_SendPlayerItemDrops, data, self.0, context),
+                CS_UM_RoundBackupFilenames => parse_and_dispatch!(CCSUsrMsg_RoundBackupFilenames, data, self.0, context),
+                CS_UM_SendPlayerItemFound => parse_and_dispatch!(CCSUsrMsg_SendPlayerItemFound, data, self.0, context),
+                CS_UM_ReportHit => parse_and_dispatch!(CCSUsrMsg_ReportHit, data, self.0, context),
+                CS_UM_XpUpdate => parse_and_dispatch!(CCSUsrMsg_XpUpdate, data, self.0, context),
+                CS_UM_QuestProgress => parse_and_dispatch!(CCSUsrMsg_QuestProgress, data, self.0, context),
+                CS_UM_ScoreLeaderboardData => parse_and_dispatch!(CCSUsrMsg_ScoreLeaderboardData, data, self.0, context),
+                CS_UM_PlayerDecalDigitalSignature => parse_and_dispatch!(CCSUsrMsg_PlayerDecalDigitalSignature, data, self.0, context),
+                CS_UM_WeaponSound => parse_and_dispatch!(CCSUsrMsg_WeaponSound, data, self.0, context),
+                CS_UM_UpdateScreenHealthBar => parse_and_dispatch!(CCSUsrMsg_UpdateScreenHealthBar, data, self.0, context),
+                CS_UM_EntityOutlineHighlight => parse_and_dispatch!(CCSUsrMsg_EntityOutlineHighlight, data, self.0, context),
+                CS_UM_SSUI => parse_and_dispatch!(CCSUsrMsg_SSUI, data, self.0, context),
+                CS_UM_SurvivalStats => parse_and_dispatch!(CCSUsrMsg_SurvivalStats, data, self.0, context),
+                CS_UM_DisconnectToLobby2 => self.0.on_disconnect_to_lobby2(&CCSUsrMsg_DisconnectToLobby::parse_from_bytes(data)?, context)?,
+                CS_UM_EndOfMatchAllPlayersData => parse_and_dispatch!(CCSUsrMsg_EndOfMatchAllPlayersData, data, self.0, context),
+                CS_UM_RoundImpactScoreData => parse_and_dispatch!(CCSUsrMsg_RoundImpactScoreData, data, self.0, context),
+                CS_UM_CurrentRoundOdds => parse_and_dispatch!(CCSUsrMsg_CurrentRoundOdds, data, self.0, context),
+                CS_UM_DeepStats => parse_and_dispatch!(CCSUsrMsg_DeepStats, data, self.0, context)
             }
         } else {
-            self.0.on_user_message(event)?;
+            self.0.dispatch(&UnknownUserMessage { msg_type: command, data }, context)?
+        };
+        Ok(control)
+    }
+
+    fn wants(&self, message: &MessageKind<'_>) -> bool {
+        self.0.wants(message)
+    }
+
+    forward_to_inner! {
+        on_dem_header => DemHeader;
+        on_packet_info => PacketInfo;
+        on_server_class => ServerClass;
+        on_server_class_registry => ServerClassRegistry;
+        on_string_tables => StringTablesSnapshot;
+        on_console_cmd => ConsoleCommand;
+        on_user_cmd => UserCmd;
+        on_custom_data => CustomData;
+        on_truncated => TruncatedDemo;
+        on_roster_change => RosterChange<'_>;
+        on_string_table_entry => StringTableEntryUpdate<'_>;
+        on_entity_update => EntityUpdate<'_>;
+        on_tick_start => TickStart;
+        on_tick_end => TickEnd<'_>;
+
+        on_nop => CNETMsg_NOP;
+        on_disconnect => CNETMsg_Disconnect;
+        on_file => CNETMsg_File;
+        on_split_screen_user => CNETMsg_SplitScreenUser;
+        on_tick => CNETMsg_Tick;
+        on_string_cmd => CNETMsg_StringCmd;
+        on_set_con_var => CNETMsg_SetConVar;
+        on_signon_state => CNETMsg_SignonState;
+        on_player_avatar_data => CNETMsg_PlayerAvatarData;
+
+        on_server_info => CSVCMsg_ServerInfo;
+        on_send_table => CSVCMsg_SendTable;
+        on_class_info => CSVCMsg_ClassInfo;
+        on_set_pause => CSVCMsg_SetPause;
+        on_create_string_table => CSVCMsg_CreateStringTable;
+        on_update_string_table => CSVCMsg_UpdateStringTable;
+        on_voice_init => CSVCMsg_VoiceInit;
+        on_voice_data => CSVCMsg_VoiceData;
+        on_print => CSVCMsg_Print;
+        on_sounds => CSVCMsg_Sounds;
+        on_set_view => CSVCMsg_SetView;
+        on_fix_angle => CSVCMsg_FixAngle;
+        on_crosshair_angle => CSVCMsg_CrosshairAngle;
+        on_bspdecal => CSVCMsg_BSPDecal;
+        on_split_screen => CSVCMsg_SplitScreen;
+        on_entity_message => CSVCMsg_EntityMsg;
+        on_game_event => CSVCMsg_GameEvent;
+        on_packet_entities => CSVCMsg_PacketEntities;
+        on_temp_entities => CSVCMsg_TempEntities;
+        on_prefetch => CSVCMsg_Prefetch;
+        on_menu => CSVCMsg_Menu;
+        on_game_event_list => CSVCMsg_GameEventList;
+        on_get_cvar_value => CSVCMsg_GetCvarValue;
+        on_paintmap_data => CSVCMsg_PaintmapData;
+        on_cmd_key_values => CSVCMsg_CmdKeyValues;
+        on_encrypted_data => CSVCMsg_EncryptedData;
+        on_hltv_replay => CSVCMsg_HltvReplay;
+        on_broadcast_command => CSVCMsg_Broadcast_Command
+    }
+}
+
+pub type CustomDataDecodeFn<T> = Box<dyn FnMut(&mut T, &CustomData, &ParserContext) -> Result<Control, Error>>;
+
+// Payloads without a decoder registered for their callback index fall through to on_custom_data.
+pub struct CustomDataDecoder<T>(pub T, HashMap<i32, CustomDataDecodeFn<T>>);
+
+impl<T> CustomDataDecoder<T> {
+    pub fn new(handler: T) -> Self {
+        CustomDataDecoder(handler, HashMap::new())
+    }
+
+    pub fn register<F>(&mut self, callback_index: i32, decoder: F)
+    where
+        F: FnMut(&mut T, &CustomData, &ParserContext) -> Result<Control, Error> + 'static
+    {
+        self.1.insert(callback_index, Box::new(decoder));
+    }
+}
+
+impl<T: EventHandler> EventHandler for CustomDataDecoder<T> {
+    fn on_custom_data(&mut self, event: &CustomData, context: &ParserContext) -> Result<Control, Error> {
+        match self.1.get_mut(&event.callback_index) {
+            Some(decoder) => decoder(&mut self.0, event, context),
+            None => self.0.on_custom_data(event, context)
         }
-        Ok(())
+    }
+
+    fn wants(&self, message: &MessageKind<'_>) -> bool {
+        self.0.wants(message)
     }
 
     forward_to_inner! {
         on_dem_header => DemHeader;
         on_packet_info => PacketInfo;
         on_server_class => ServerClass;
+        on_server_class_registry => ServerClassRegistry;
+        on_string_tables => StringTablesSnapshot;
+        on_console_cmd => ConsoleCommand;
+        on_user_cmd => UserCmd;
+        on_truncated => TruncatedDemo;
+        on_roster_change => RosterChange<'_>;
+        on_string_table_entry => StringTableEntryUpdate<'_>;
+        on_entity_update => EntityUpdate<'_>;
+        on_tick_start => TickStart;
+        on_tick_end => TickEnd<'_>;
 
         on_nop => CNETMsg_NOP;
         on_disconnect => CNETMsg_Disconnect;
@@ -296,6 +510,7 @@ impl<T: UserMessageEventHandler> EventHandler for UserMessageDecoder<T> {
         on_crosshair_angle => CSVCMsg_CrosshairAngle;
         on_bspdecal => CSVCMsg_BSPDecal;
         on_split_screen => CSVCMsg_SplitScreen;
+        on_user_message => CSVCMsg_UserMessage;
         on_entity_message => CSVCMsg_EntityMsg;
         on_game_event => CSVCMsg_GameEvent;
         on_packet_entities => CSVCMsg_PacketEntities;
@@ -318,6 +533,17 @@ impl_dispatch! {
     on_dem_header => DemHeader;
     on_packet_info => PacketInfo;
     on_server_class => ServerClass;
+    on_server_class_registry => ServerClassRegistry;
+    on_string_tables => StringTablesSnapshot;
+    on_console_cmd => ConsoleCommand;
+    on_user_cmd => UserCmd;
+    on_custom_data => CustomData;
+    on_truncated => TruncatedDemo;
+    on_roster_change => RosterChange<'_>;
+    on_string_table_entry => StringTableEntryUpdate<'_>;
+    on_entity_update => EntityUpdate<'_>;
+    on_tick_start => TickStart;
+    on_tick_end => TickEnd<'_>;
 
     on_nop => CNETMsg_NOP;
     on_disconnect => CNETMsg_Disconnect;
@@ -389,13 +615,14 @@ impl_dispatch! {
     on_process_spotted_entity_update => CCSUsrMsg_ProcessSpottedEntityUpdate;
     on_reload_effect => CCSUsrMsg_ReloadEffect;
     on_adjust_money => CCSUsrMsg_AdjustMoney;
-    //on_UpdateTeamMoney => CCSUsrMsg_UpdateTeamMoney;
+    on_update_team_money => UpdateTeamMoney<'_>;
     on_stop_spectator_mode => CCSUsrMsg_StopSpectatorMode;
     on_kill_cam => CCSUsrMsg_KillCam;
     on_desired_timescale => CCSUsrMsg_DesiredTimescale;
     on_current_timescale => CCSUsrMsg_CurrentTimescale;
     on_achievement_event => CCSUsrMsg_AchievementEvent;
     on_match_end_conditions => CCSUsrMsg_MatchEndConditions;
+    // CS_UM_DisconnectToLobby2 carries the same message, so on_disconnect_to_lobby2 is called directly.
     on_disconnect_to_lobby => CCSUsrMsg_DisconnectToLobby;
     on_player_stats_update => CCSUsrMsg_PlayerStatsUpdate;
     on_display_inventory => CCSUsrMsg_DisplayInventory;
@@ -432,9 +659,17 @@ impl_dispatch! {
     on_entity_outline_highlight => CCSUsrMsg_EntityOutlineHighlight;
     on_ssui => CCSUsrMsg_SSUI;
     on_survival_stats => CCSUsrMsg_SurvivalStats;
-    //on_DisconnectToLobby2 => CCSUsrMsg_DisconnectToLobby2;
     on_end_of_match_all_players_data => CCSUsrMsg_EndOfMatchAllPlayersData;
     on_round_impact_score_data => CCSUsrMsg_RoundImpactScoreData;
     on_current_round_odds => CCSUsrMsg_CurrentRoundOdds;
-    on_deep_stats => CCSUsrMsg_DeepStats
+    on_deep_stats => CCSUsrMsg_DeepStats;
+
+    on_unknown_user_message => UnknownUserMessage<'_>
 }
+
+mod de;
+mod fan_out;
+mod game_events;
+mod prop_watcher;
+pub use self::game_events::*;
+pub use self::prop_watcher::*;
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use super::*;
+use crate::entities::{Entity, EntityUpdateKind, PropValue};
+use crate::sendtables::FlattenedProp;
+use crate::DemoError;
+
+#[derive(Copy, Clone, Debug)]
+pub struct PropChange<'a> {
+    // The path the prop was watched with.
+    pub path: &'a str,
+    pub entity: &'a Entity,
+    pub prop: &'a FlattenedProp,
+    // None for the first value seen, e.g. when the entity is created.
+    pub old_value: Option<&'a PropValue>,
+    pub new_value: &'a PropValue,
+    pub tick: i32
+}
+
+pub trait PropChangeHandler: EventHandler {
+    on_fn! {
+        on_prop_change => PropChange<'_>
+    }
+}
+
+#[derive(Default)]
+struct PropWatches {
+    paths: Vec<String>,
+    // Watched prop indices of each class id, along with the index of their path.
+    classes: HashMap<u16, Vec<(usize, usize)>>,
+    // Last value reported for an entity index and prop index.
+    values: HashMap<(u32, usize), PropValue>
+}
+
+impl PropWatches {
+    fn resolve(&mut self, registry: &ServerClassRegistry) -> Result<(), Error> {
+        self.classes.clear();
+        self.values.clear();
+
+        for (path_index, path) in self.paths.iter().enumerate() {
+            let (class_name, prop_name) = path.split_once('.').ok_or_else(|| {
+                DemoError::invalid_data(format!("Prop path {} is not of the form Class.prop", path))
+            })?;
+
+            let mut found = false;
+            for class in registry.classes().iter().filter(|class| class.name == class_name || class.datatable == class_name) {
+                if let Some(prop_index) = class.prop_index(prop_name) {
+                    self.classes.entry(class.id).or_default().push((prop_index, path_index));
+                    found = true;
+                }
+            }
+
+            if !found {
+                return Err(DemoError::invalid_data(format!("Prop path {} does not name a prop of any server class", path)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Reports changes of individual entity props through on_prop_change. Needs
+// ParseOptions::decode_entities, otherwise there are no entity updates to watch.
+pub struct PropWatcher<T>(pub T, PropWatches);
+
+impl<T> PropWatcher<T> {
+    pub fn new(handler: T) -> Self {
+        PropWatcher(handler, PropWatches::default())
+    }
+
+    // Paths name a server class or its data table and a flattened prop, e.g.
+    // `CCSPlayer.m_iHealth` or `DT_CSTeam.m_scoreTotal`. They are resolved once the data
+    // tables have been parsed, paths that match nothing fail the parse at that point.
+    pub fn watch<S: Into<String>>(&mut self, path: S) {
+        self.1.paths.push(path.into());
+    }
+}
+
+impl<T: PropChangeHandler> EventHandler for PropWatcher<T> {
+    fn on_server_class_registry(&mut self, event: &ServerClassRegistry, context: &ParserContext) -> Result<Control, Error> {
+        self.1.resolve(event)?;
+        self.0.on_server_class_registry(event, context)
+    }
+
+    fn on_entity_update(&mut self, event: &EntityUpdate, context: &ParserContext) -> Result<Control, Error> {
+        let mut control = self.0.on_entity_update(event, context)?;
+
+        let entity = event.entity;
+        let watched = match self.1.classes.get(&entity.class_id()) {
+            Some(watched) => watched,
+            None => return Ok(control)
+        };
+
+        for &(prop_index, path_index) in watched {
+            let key = (entity.index, prop_index);
+
+            match event.kind {
+                EntityUpdateKind::Created => {
+                    self.1.values.remove(&key);
+                },
+                EntityUpdateKind::Updated if event.changed_props.contains(&prop_index) => {},
+                EntityUpdateKind::Deleted => {
+                    self.1.values.remove(&key);
+                    continue;
+                },
+                _ => continue
+            }
+
+            let new_value = match entity.prop_at(prop_index) {
+                Some(value) => value,
+                None => continue
+            };
+            let old_value = self.1.values.get(&key);
+            if old_value == Some(new_value) {
+                continue;
+            }
+
+            let change = PropChange {
+                path: &self.1.paths[path_index],
+                entity,
+                prop: &entity.class.props[prop_index],
+                old_value,
+                new_value,
+                tick: context.tick
+            };
+            control = control.max(self.0.on_prop_change(&change, context)?);
+
+            self.1.values.insert(key, new_value.clone());
+        }
+
+        Ok(control)
+    }
+
+    fn wants(&self, message: &MessageKind<'_>) -> bool {
+        self.0.wants(message)
+    }
+
+    forward_to_inner! {
+        on_dem_header => DemHeader;
+        on_packet_info => PacketInfo;
+        on_server_class => ServerClass;
+        on_string_tables => StringTablesSnapshot;
+        on_console_cmd => ConsoleCommand;
+        on_user_cmd => UserCmd;
+        on_custom_data => CustomData;
+        on_truncated => TruncatedDemo;
+        on_roster_change => RosterChange<'_>;
+        on_string_table_entry => StringTableEntryUpdate<'_>;
+        on_tick_start => TickStart;
+        on_tick_end => TickEnd<'_>;
+
+        on_nop => CNETMsg_NOP;
+        on_disconnect => CNETMsg_Disconnect;
+        on_file => CNETMsg_File;
+        on_split_screen_user => CNETMsg_SplitScreenUser;
+        on_tick => CNETMsg_Tick;
+        on_string_cmd => CNETMsg_StringCmd;
+        on_set_con_var => CNETMsg_SetConVar;
+        on_signon_state => CNETMsg_SignonState;
+        on_player_avatar_data => CNETMsg_PlayerAvatarData;
+
+        on_server_info => CSVCMsg_ServerInfo;
+        on_send_table => CSVCMsg_SendTable;
+        on_class_info => CSVCMsg_ClassInfo;
+        on_set_pause => CSVCMsg_SetPause;
+        on_create_string_table => CSVCMsg_CreateStringTable;
+        on_update_string_table => CSVCMsg_UpdateStringTable;
+        on_voice_init => CSVCMsg_VoiceInit;
+        on_voice_data => CSVCMsg_VoiceData;
+        on_print => CSVCMsg_Print;
+        on_sounds => CSVCMsg_Sounds;
+        on_set_view => CSVCMsg_SetView;
+        on_fix_angle => CSVCMsg_FixAngle;
+        on_crosshair_angle => CSVCMsg_CrosshairAngle;
+        on_bspdecal => CSVCMsg_BSPDecal;
+        on_split_screen => CSVCMsg_SplitScreen;
+        on_user_message => CSVCMsg_UserMessage;
+        on_entity_message => CSVCMsg_EntityMsg;
+        on_game_event => CSVCMsg_GameEvent;
+        on_packet_entities => CSVCMsg_PacketEntities;
+        on_temp_entities => CSVCMsg_TempEntities;
+        on_prefetch => CSVCMsg_Prefetch;
+        on_menu => CSVCMsg_Menu;
+        on_game_event_list => CSVCMsg_GameEventList;
+        on_get_cvar_value => CSVCMsg_GetCvarValue;
+        on_paintmap_data => CSVCMsg_PaintmapData;
+        on_cmd_key_values => CSVCMsg_CmdKeyValues;
+        on_encrypted_data => CSVCMsg_EncryptedData;
+        on_hltv_replay => CSVCMsg_HltvReplay;
+        on_broadcast_command => CSVCMsg_Broadcast_Command
+    }
+}
+
+impl<T: PropChangeHandler + UserMessageEventHandler> PropChangeHandler for UserMessageDecoder<T> {
+    forward_to_inner! {
+        on_prop_change => PropChange<'_>
+    }
+}
+
+impl<T: PropChangeHandler + GameEventHandler> PropChangeHandler for GameEventDecoder<T> {
+    forward_to_inner! {
+        on_prop_change => PropChange<'_>
+    }
+}
+
+impl<T: PropChangeHandler> PropChangeHandler for CustomDataDecoder<T> {
+    forward_to_inner! {
+        on_prop_change => PropChange<'_>
+    }
+}
+
+impl_dispatch! {
+    PropChangeHandler;
+
+    on_prop_change => PropChange<'_>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitreader::BitWriter;
+    use crate::entities::testing::*;
+    use crate::entities::EntityTable;
+
+    #[derive(Default)]
+    struct Recorder {
+        changes: Vec<(String, u32, Option<PropValue>, PropValue)>
+    }
+
+    impl EventHandler for Recorder {}
+
+    impl PropChangeHandler for Recorder {
+        fn on_prop_change(&mut self, event: &PropChange<'_>, _: &ParserContext) -> Result<Control, Error> {
+            self.changes.push((event.path.to_owned(), event.entity.index, event.old_value.cloned(), event.new_value.clone()));
+            Ok(Control::Continue)
+        }
+    }
+
+    fn test_registry() -> ServerClassRegistry {
+        registry(&[
+            ("CCSPlayer", "DT_CSPlayer", &[("m_iHealth", 8), ("m_ArmorValue", 8)]),
+            ("CCSTeam", "DT_CSTeam", &[("m_scoreTotal", 8)])
+        ])
+    }
+
+    fn watcher(registry: &ServerClassRegistry, paths: &[&str]) -> Result<PropWatcher<Recorder>, Error> {
+        let mut watcher = PropWatcher::new(Recorder::default());
+        for path in paths {
+            watcher.watch(*path);
+        }
+        watcher.on_server_class_registry(registry, &ParserContext::default())?;
+        Ok(watcher)
+    }
+
+    fn read(watcher: &mut PropWatcher<Recorder>, entities: &mut EntityTable, registry: &ServerClassRegistry, message: &CSVCMsg_PacketEntities) -> Vec<(String, u32, Option<PropValue>, PropValue)> {
+        let context = ParserContext::default();
+        entities.read_packet_entities(registry, message, |update| watcher.on_entity_update(update, &context).map(|_| ())).unwrap();
+        watcher.0.changes.drain(..).collect()
+    }
+
+    #[test]
+    fn reports_watched_props_by_class_and_datatable() {
+        let registry = test_registry();
+        let mut watcher = watcher(&registry, &["CCSPlayer.m_iHealth", "DT_CSTeam.m_scoreTotal"]).unwrap();
+        let mut entities = EntityTable::new();
+
+        let writer = enter(BitWriter::default(), &registry, 1, "CCSPlayer", 1, &[("m_iHealth", 100), ("m_ArmorValue", 100)]);
+        let writer = enter(writer, &registry, 0, "CCSTeam", 1, &[("m_scoreTotal", 0)]);
+        assert_eq!(read(&mut watcher, &mut entities, &registry, &packet(true, 2, writer)), vec![
+            ("CCSPlayer.m_iHealth".to_owned(), 1, None, PropValue::Int(100)),
+            ("DT_CSTeam.m_scoreTotal".to_owned(), 2, None, PropValue::Int(0))
+        ]);
+
+        let player = registry.class_by_name("CCSPlayer").unwrap();
+        let writer = update(BitWriter::default(), player, 1, &[("m_iHealth", 73), ("m_ArmorValue", 50)]);
+        assert_eq!(read(&mut watcher, &mut entities, &registry, &packet(true, 1, writer)), vec![
+            ("CCSPlayer.m_iHealth".to_owned(), 1, Some(PropValue::Int(100)), PropValue::Int(73))
+        ]);
+    }
+
+    #[test]
+    fn ignores_unchanged_and_unwatched_props() {
+        let registry = test_registry();
+        let mut watcher = watcher(&registry, &["CCSPlayer.m_iHealth"]).unwrap();
+        let mut entities = EntityTable::new();
+
+        let writer = enter(BitWriter::default(), &registry, 1, "CCSPlayer", 1, &[("m_iHealth", 100)]);
+        read(&mut watcher, &mut entities, &registry, &packet(true, 1, writer));
+
+        let player = registry.class_by_name("CCSPlayer").unwrap();
+        let writer = update(BitWriter::default(), player, 1, &[("m_ArmorValue", 50)]);
+        assert!(read(&mut watcher, &mut entities, &registry, &packet(true, 1, writer)).is_empty());
+
+        // Sent again with the value it already had.
+        let writer = update(BitWriter::default(), player, 1, &[("m_iHealth", 100)]);
+        assert!(read(&mut watcher, &mut entities, &registry, &packet(true, 1, writer)).is_empty());
+    }
+
+    #[test]
+    fn rejects_paths_that_match_no_prop() {
+        let registry = test_registry();
+        assert!(watcher(&registry, &["CCSPlayer.m_iMissing"]).is_err());
+        assert!(watcher(&registry, &["CCSBot.m_iHealth"]).is_err());
+        assert!(watcher(&registry, &["m_iHealth"]).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::convert::TryInto;
 use std::io::Read;
 
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
@@ -101,7 +102,63 @@ pub struct ServerClass {
     pub datatable: String
 }
 
-#[repr(C, packed)]
+#[derive(Clone, Debug, Serialize)]
+pub struct ConsoleCommand {
+    pub tick: i32,
+    pub player_slot: u8,
+    pub command: String
+}
+
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct UserCmd {
+    pub tick: i32,
+    pub player_slot: u8,
+    pub sequence: i32,
+    pub command_number: i32,
+    pub tick_count: i32,
+    pub view_angles: [f32; 3],
+    pub aim_direction: [f32; 3],
+    pub forward_move: f32,
+    pub side_move: f32,
+    pub up_move: f32,
+    pub buttons: u32,
+    pub impulse: u8,
+    pub weapon_select: u32,
+    pub weapon_subtype: u32,
+    pub mouse_dx: i16,
+    pub mouse_dy: i16
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CustomData {
+    pub tick: i32,
+    pub callback_index: i32,
+    pub data: Vec<u8>
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct TruncatedDemo {
+    pub last_tick: i32
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StringTableEntry {
+    pub name: String,
+    pub user_data: Option<Vec<u8>>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StringTableSnapshot {
+    pub name: String,
+    pub entries: Vec<StringTableEntry>,
+    pub client_entries: Vec<StringTableEntry>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StringTablesSnapshot {
+    pub tables: Vec<StringTableSnapshot>
+}
+
 #[serde_as]
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct PlayerInfo {
@@ -117,11 +174,57 @@ pub struct PlayerInfo {
     pub friends_name: [u8; 128],
     pub fake_player: bool,
     pub is_hltv: bool,
-    pub custom_files: [u64; 4],
-    pub files_downloaded: u8,
-    pub entity_id: i32
+    pub custom_files: [u32; 4],
+    pub files_downloaded: u8
+}
+
+// Offset of files_downloaded, the last field of player_info_t, plus one.
+const PLAYER_INFO_SIZE: usize = 337;
+
+fn c_string(bytes: &[u8]) -> String {
+    let length = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..length]).into_owned()
+}
+
+impl PlayerInfo {
+    pub fn name(&self) -> String {
+        c_string(&self.name)
+    }
+
+    pub fn guid(&self) -> String {
+        c_string(&self.guid)
+    }
+
+    pub fn friends_name(&self) -> String {
+        c_string(&self.friends_name)
+    }
+}
+
+// player_info_t keeps the engine's natural alignment and is byte swapped to big
+// endian, so it cannot go through impl_parse! like the packed structs above.
+impl Parse for PlayerInfo {
+    fn parse<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let mut data = [0u8; PLAYER_INFO_SIZE];
+        reader.read_exact(&mut data)?;
+
+        let u32_at = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let u64_at = |offset: usize| u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        Ok(PlayerInfo {
+            version: u64_at(0),
+            xuid: u64_at(8),
+            name: data[16..144].try_into().unwrap(),
+            user_id: u32_at(144) as i32,
+            guid: data[148..181].try_into().unwrap(),
+            friends_id: u32_at(184),
+            friends_name: data[188..316].try_into().unwrap(),
+            fake_player: data[316] != 0,
+            is_hltv: data[317] != 0,
+            custom_files: [u32_at(320), u32_at(324), u32_at(328), u32_at(332)],
+            files_downloaded: data[336]
+        })
+    }
 }
-impl_parse!(PlayerInfo);
 
 #[repr(C, packed)]
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
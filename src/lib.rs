@@ -1,151 +1,385 @@
-
+mod error;
 mod format;
 mod util;
+mod bitreader;
+mod compression;
 mod protos;
 
 use std::io::Read;
 
 use protobuf::{ Message, ProtobufEnum };
-use util::{ReadExt, read_varuint};
+use util::{CountingReader, ReadExt, read_varuint};
 
 use format::*;
 use protos::netmessages::*;
 
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub use error::{DemoError, ErrorContext};
+pub use bitreader::BitReader;
+pub type Error = DemoError;
 
 pub mod events;
-use events::{ EventHandler, Dispatcher };
+pub mod sendtables;
+pub mod props;
+pub mod stringtables;
+pub mod players;
+pub mod entities;
+pub mod parser;
+pub mod views;
+use events::{ Control, EventHandler, Dispatcher, MessageKind, ParserContext, TickStart, TickEnd };
+use sendtables::ServerClassRegistry;
+use entities::EntityTable;
+use stringtables::{StringTable, StringTables};
+use players::PlayerRoster;
+
+pub use parser::{DemoEvent, DemoEventKind, DemoParser};
+pub use demo_macros::demo_handler;
 
-macro_rules! read_varuint_and_dec {
-    ($reader:ident, $size:ident) => {
+macro_rules! parse_and_dispatch {
+    ($ident:ident, $reader:ident, $dispatcher:ident, $context:expr) => {
         {
-            let data = read_varuint($reader)?;
-            $size -= data.1;
-            data.0
+            let event = $ident::parse_from_reader($reader)?;
+            $dispatcher.dispatch(&event, $context)?
         }
     };
 }
 
-macro_rules! parse_and_dispatch {
-    ($ident:ident, $reader:ident, $dispatcher:ident) => {
-        {
-            let event = $ident::parse_from_reader($reader)?;
-            $dispatcher.dispatch(&event)?;
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ParseOptions {
+    // Upper bound for any length-prefixed block read from the file.
+    pub max_frame_size: u32,
+    // Treat running out of input as the end of the demo instead of an error and skip
+    // string table updates that cannot be decoded, see StringTable::skipped_updates.
+    pub lenient: bool,
+    // Keep the contents of every string table and dispatch StringTableEntryUpdates.
+    // The player roster is built from the userinfo table and needs this as well.
+    pub decode_string_tables: bool,
+    // Decode svc_PacketEntities into a live entity table and dispatch EntityUpdates.
+    // Implies decode_string_tables, entities need the instancebaseline table.
+    pub decode_entities: bool
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            lenient: false,
+            decode_string_tables: false,
+            decode_entities: false
+        }
+    }
+}
+
+impl ParseOptions {
+    fn decodes_string_tables(&self) -> bool {
+        self.decode_string_tables || self.decode_entities
+    }
+}
+
+// Everything that has to survive from one frame to the next.
+struct ParserState {
+    registry: Option<ServerClassRegistry>,
+    string_tables: StringTables,
+    roster: PlayerRoster,
+    entities: EntityTable,
+    context: ParserContext,
+    frames: u32
+}
+
+impl ParserState {
+    fn new(options: &ParseOptions) -> Self {
+        ParserState {
+            registry: None,
+            string_tables: StringTables::with_options(options),
+            roster: PlayerRoster::new(),
+            entities: EntityTable::new(),
+            context: ParserContext::default(),
+            frames: 0
         }
+    }
+
+    // Whether the frame belongs to a new tick while the previous one has not been ended.
+    fn ends_tick(&self, command_header: &CommandHeader) -> bool {
+        self.frames > 0 && command_header.tick != self.context.tick
+    }
+
+    // Returns whether the frame is the first one of its tick.
+    fn begin_frame(&mut self, command_header: &CommandHeader) -> bool {
+        let starts_tick = self.frames == 0 || command_header.tick != self.context.tick;
+
+        self.context.tick = command_header.tick;
+        self.context.player_slot = command_header.player_slot;
+        self.context.frame = self.frames;
+        self.frames += 1;
+        starts_tick
+    }
+}
+
+fn end_tick<D: EventHandler>(dispatcher: &mut D, state: &ParserState) -> Result<Control, Error> {
+    let tick_end = TickEnd {
+        tick: state.context.tick,
+        server_classes: state.registry.as_ref(),
+        string_tables: &state.string_tables,
+        players: &state.roster,
+        entities: &state.entities
     };
+
+    dispatcher.dispatch(&tick_end, &state.context)
 }
 
-fn parse_net_command<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &D, command: NET_Messages) -> Result<(), Error> {
-    use NET_Messages::*;
+const INSTANCE_BASELINE_TABLE: &str = "instancebaseline";
+const USER_INFO_TABLE: &str = "userinfo";
 
-    match command {
-        net_NOP => parse_and_dispatch!(CNETMsg_NOP, reader, dispatcher),
-        net_Disconnect => parse_and_dispatch!(CNETMsg_Disconnect, reader, dispatcher),
-        net_File => parse_and_dispatch!(CNETMsg_File, reader, dispatcher),
-        net_SplitScreenUser => parse_and_dispatch!(CNETMsg_SplitScreenUser, reader, dispatcher),
-        net_Tick => parse_and_dispatch!(CNETMsg_Tick, reader, dispatcher),
-        net_StringCmd => parse_and_dispatch!(CNETMsg_StringCmd, reader, dispatcher),
-        net_SetConVar => parse_and_dispatch!(CNETMsg_SetConVar, reader, dispatcher),
-        net_SignonState => parse_and_dispatch!(CNETMsg_SignonState, reader, dispatcher),
-        net_PlayerAvatarData => parse_and_dispatch!(CNETMsg_PlayerAvatarData, reader, dispatcher),
-    }
-
-    assert_eq!(reader.read(&mut [0u8; 1])?, 0);
+fn read_data<R: Read + Sized>(reader: &mut R, options: &ParseOptions) -> Result<Vec<u8>, Error> {
+    let data_header = DataHeader::parse(reader)?;
+    let size = data_header.size;
+
+    if size < 0 {
+        return Err(DemoError::invalid_data(format!("Negative data size {}", size)));
+    }
+    if size as u32 > options.max_frame_size {
+        return Err(DemoError::limit_exceeded(size as u64, options.max_frame_size as u64));
+    }
+
+    let mut data = vec![0u8; size as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn split_data<'a>(data: &mut &'a [u8], size: usize) -> Result<&'a [u8], Error> {
+    if size > data.len() {
+        return Err(DemoError::size_mismatch(size as u64, data.len() as u64));
+    }
+
+    let (head, tail) = data.split_at(size);
+    *data = tail;
+    Ok(head)
+}
+
+fn expect_consumed(data: &[u8]) -> Result<(), Error> {
+    if !data.is_empty() {
+        return Err(DemoError::size_mismatch(0, data.len() as u64));
+    }
     Ok(())
 }
 
-fn parse_svc_command<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &D, command: SVC_Messages) -> Result<(), Error> {
+fn parse_net_command<D: EventHandler>(reader: &mut &[u8], dispatcher: &mut D, command: NET_Messages, state: &mut ParserState) -> Result<Control, Error> {
+    use NET_Messages::*;
+
+    let control = match command {
+        net_NOP => parse_and_dispatch!(CNETMsg_NOP, reader, dispatcher, &state.context),
+        net_Disconnect => parse_and_dispatch!(CNETMsg_Disconnect, reader, dispatcher, &state.context),
+        net_File => parse_and_dispatch!(CNETMsg_File, reader, dispatcher, &state.context),
+        net_SplitScreenUser => parse_and_dispatch!(CNETMsg_SplitScreenUser, reader, dispatcher, &state.context),
+        net_Tick => {
+            let message = CNETMsg_Tick::parse_from_reader(reader)?;
+            state.context.server_tick = message.get_tick() as i32;
+            dispatcher.dispatch(&message, &state.context)?
+        },
+        net_StringCmd => parse_and_dispatch!(CNETMsg_StringCmd, reader, dispatcher, &state.context),
+        net_SetConVar => parse_and_dispatch!(CNETMsg_SetConVar, reader, dispatcher, &state.context),
+        net_SignonState => parse_and_dispatch!(CNETMsg_SignonState, reader, dispatcher, &state.context),
+        net_PlayerAvatarData => parse_and_dispatch!(CNETMsg_PlayerAvatarData, reader, dispatcher, &state.context),
+    };
+
+    expect_consumed(reader)?;
+    Ok(control)
+}
+
+fn on_string_table_entry<D: EventHandler>(dispatcher: &mut D, roster: &mut PlayerRoster, entities: &mut EntityTable, context: &ParserContext, table: &StringTable, index: usize) -> Result<Control, Error> {
+    let mut control = Control::Continue;
+
+    if let Some(update) = table.entry_update(index) {
+        control = dispatcher.dispatch(&update, context)?;
+
+        match update.table {
+            INSTANCE_BASELINE_TABLE => {
+                if let Some(user_data) = update.user_data {
+                    entities.set_baseline(update.key, user_data.to_vec());
+                }
+            },
+            USER_INFO_TABLE => roster.update(index, update.user_data, |change| {
+                control = control.max(dispatcher.dispatch(change, context)?);
+                Ok(())
+            })?,
+            _ => {}
+        }
+    }
+    Ok(control)
+}
+
+fn parse_create_string_table<D: EventHandler>(reader: &mut &[u8], dispatcher: &mut D, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    let message = CSVCMsg_CreateStringTable::parse_from_reader(reader)?;
+    let mut control = dispatcher.dispatch(&message, &state.context)?;
+
+    if options.decodes_string_tables() {
+        let (roster, entities, context) = (&mut state.roster, &mut state.entities, &state.context);
+        state.string_tables.create(&message, |table, index| {
+            control = control.max(on_string_table_entry(dispatcher, roster, entities, context, table, index)?);
+            Ok(())
+        })?;
+    }
+
+    Ok(control)
+}
+
+fn parse_update_string_table<D: EventHandler>(reader: &mut &[u8], dispatcher: &mut D, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    let message = CSVCMsg_UpdateStringTable::parse_from_reader(reader)?;
+    let mut control = dispatcher.dispatch(&message, &state.context)?;
+
+    if options.decodes_string_tables() {
+        let (roster, entities, context) = (&mut state.roster, &mut state.entities, &state.context);
+        state.string_tables.update(&message, |table, index| {
+            control = control.max(on_string_table_entry(dispatcher, roster, entities, context, table, index)?);
+            Ok(())
+        })?;
+    }
+
+    Ok(control)
+}
+
+fn parse_packet_entities<D: EventHandler>(reader: &mut &[u8], dispatcher: &mut D, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    let message = CSVCMsg_PacketEntities::parse_from_reader(reader)?;
+    let mut control = dispatcher.dispatch(&message, &state.context)?;
+
+    if options.decode_entities {
+        let registry = state.registry.as_ref().ok_or_else(|| DemoError::invalid_data("Packet entities before data tables"))?;
+        let context = &state.context;
+        state.entities.read_packet_entities(registry, &message, |update| {
+            control = control.max(dispatcher.dispatch(update, context)?);
+            Ok(())
+        })?;
+    }
+
+    Ok(control)
+}
+
+fn parse_svc_command<D: EventHandler>(reader: &mut &[u8], dispatcher: &mut D, command: SVC_Messages, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    use SVC_Messages::*;
+
+    let control = match command {
+        svc_ServerInfo => {
+            let message = CSVCMsg_ServerInfo::parse_from_reader(reader)?;
+            state.context.tick_interval = message.get_tick_interval();
+            dispatcher.dispatch(&message, &state.context)?
+        },
+        svc_SendTable => parse_and_dispatch!(CSVCMsg_SendTable, reader, dispatcher, &state.context),
+        svc_ClassInfo => parse_and_dispatch!(CSVCMsg_ClassInfo, reader, dispatcher, &state.context),
+        svc_SetPause => parse_and_dispatch!(CSVCMsg_SetPause, reader, dispatcher, &state.context),
+        svc_CreateStringTable => parse_create_string_table(reader, dispatcher, options, state)?,
+        svc_UpdateStringTable => parse_update_string_table(reader, dispatcher, options, state)?,
+        svc_VoiceInit => parse_and_dispatch!(CSVCMsg_VoiceInit, reader, dispatcher, &state.context),
+        svc_VoiceData => parse_and_dispatch!(CSVCMsg_VoiceData, reader, dispatcher, &state.context),
+        svc_Print => parse_and_dispatch!(CSVCMsg_Print, reader, dispatcher, &state.context),
+        svc_Sounds => parse_and_dispatch!(CSVCMsg_Sounds, reader, dispatcher, &state.context),
+        svc_SetView => parse_and_dispatch!(CSVCMsg_SetView, reader, dispatcher, &state.context),
+        svc_FixAngle => parse_and_dispatch!(CSVCMsg_FixAngle, reader, dispatcher, &state.context),
+        svc_CrosshairAngle => parse_and_dispatch!(CSVCMsg_CrosshairAngle, reader, dispatcher, &state.context),
+        svc_BSPDecal => parse_and_dispatch!(CSVCMsg_BSPDecal, reader, dispatcher, &state.context),
+        svc_SplitScreen => parse_and_dispatch!(CSVCMsg_SplitScreen, reader, dispatcher, &state.context),
+        svc_UserMessage => parse_and_dispatch!(CSVCMsg_UserMessage, reader, dispatcher, &state.context),
+        svc_EntityMessage => parse_and_dispatch!(CSVCMsg_EntityMsg, reader, dispatcher, &state.context),
+        svc_GameEvent => parse_and_dispatch!(CSVCMsg_GameEvent, reader, dispatcher, &state.context),
+        svc_PacketEntities => parse_packet_entities(reader, dispatcher, options, state)?,
+        svc_TempEntities => parse_and_dispatch!(CSVCMsg_TempEntities, reader, dispatcher, &state.context),
+        svc_Prefetch => parse_and_dispatch!(CSVCMsg_Prefetch, reader, dispatcher, &state.context),
+        svc_Menu => parse_and_dispatch!(CSVCMsg_Menu, reader, dispatcher, &state.context),
+        svc_GameEventList => parse_and_dispatch!(CSVCMsg_GameEventList, reader, dispatcher, &state.context),
+        svc_GetCvarValue => parse_and_dispatch!(CSVCMsg_GetCvarValue, reader, dispatcher, &state.context),
+        svc_PaintmapData => parse_and_dispatch!(CSVCMsg_PaintmapData, reader, dispatcher, &state.context),
+        svc_CmdKeyValues => parse_and_dispatch!(CSVCMsg_CmdKeyValues, reader, dispatcher, &state.context),
+        svc_EncryptedData => parse_and_dispatch!(CSVCMsg_EncryptedData, reader, dispatcher, &state.context),
+        svc_HltvReplay => parse_and_dispatch!(CSVCMsg_HltvReplay, reader, dispatcher, &state.context),
+        svc_Broadcast_Command => parse_and_dispatch!(CSVCMsg_Broadcast_Command, reader, dispatcher, &state.context),
+    };
+
+    expect_consumed(reader)?;
+    Ok(control)
+}
+
+// Messages that keep the parser's own state up to date are decoded even when unwanted.
+fn needs_svc_command(command: SVC_Messages, options: &ParseOptions) -> bool {
     use SVC_Messages::*;
 
     match command {
-        svc_ServerInfo => parse_and_dispatch!(CSVCMsg_ServerInfo, reader, dispatcher),
-        svc_SendTable => parse_and_dispatch!(CSVCMsg_SendTable, reader, dispatcher),
-        svc_ClassInfo => parse_and_dispatch!(CSVCMsg_ClassInfo, reader, dispatcher),
-        svc_SetPause => parse_and_dispatch!(CSVCMsg_SetPause, reader, dispatcher),
-        svc_CreateStringTable => parse_and_dispatch!(CSVCMsg_CreateStringTable, reader, dispatcher),
-        svc_UpdateStringTable => parse_and_dispatch!(CSVCMsg_UpdateStringTable, reader, dispatcher),
-        svc_VoiceInit => parse_and_dispatch!(CSVCMsg_VoiceInit, reader, dispatcher),
-        svc_VoiceData => parse_and_dispatch!(CSVCMsg_VoiceData, reader, dispatcher),
-        svc_Print => parse_and_dispatch!(CSVCMsg_Print, reader, dispatcher),
-        svc_Sounds => parse_and_dispatch!(CSVCMsg_Sounds, reader, dispatcher),
-        svc_SetView => parse_and_dispatch!(CSVCMsg_SetView, reader, dispatcher),
-        svc_FixAngle => parse_and_dispatch!(CSVCMsg_FixAngle, reader, dispatcher),
-        svc_CrosshairAngle => parse_and_dispatch!(CSVCMsg_CrosshairAngle, reader, dispatcher),
-        svc_BSPDecal => parse_and_dispatch!(CSVCMsg_BSPDecal, reader, dispatcher),
-        svc_SplitScreen => parse_and_dispatch!(CSVCMsg_SplitScreen, reader, dispatcher),
-        svc_UserMessage => parse_and_dispatch!(CSVCMsg_UserMessage, reader, dispatcher),
-        svc_EntityMessage => parse_and_dispatch!(CSVCMsg_EntityMsg, reader, dispatcher),
-        svc_GameEvent => parse_and_dispatch!(CSVCMsg_GameEvent, reader, dispatcher),
-        svc_PacketEntities => parse_and_dispatch!(CSVCMsg_PacketEntities, reader, dispatcher),
-        svc_TempEntities => parse_and_dispatch!(CSVCMsg_TempEntities, reader, dispatcher),
-        svc_Prefetch => parse_and_dispatch!(CSVCMsg_Prefetch, reader, dispatcher),
-        svc_Menu => parse_and_dispatch!(CSVCMsg_Menu, reader, dispatcher),
-        svc_GameEventList => parse_and_dispatch!(CSVCMsg_GameEventList, reader, dispatcher),
-        svc_GetCvarValue => parse_and_dispatch!(CSVCMsg_GetCvarValue, reader, dispatcher),
-        svc_PaintmapData => parse_and_dispatch!(CSVCMsg_PaintmapData, reader, dispatcher),
-        svc_CmdKeyValues => parse_and_dispatch!(CSVCMsg_CmdKeyValues, reader, dispatcher),
-        svc_EncryptedData => parse_and_dispatch!(CSVCMsg_EncryptedData, reader, dispatcher),
-        svc_HltvReplay => parse_and_dispatch!(CSVCMsg_HltvReplay, reader, dispatcher),
-        svc_Broadcast_Command => parse_and_dispatch!(CSVCMsg_Broadcast_Command, reader, dispatcher),
-    }
-
-    assert_eq!(reader.read(&mut [0u8; 1])?, 0);
-    Ok(())
+        svc_ServerInfo => true,
+        svc_CreateStringTable | svc_UpdateStringTable => options.decodes_string_tables(),
+        svc_PacketEntities => options.decode_entities,
+        _ => false
+    }
 }
 
-fn parse_command<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &D, command: i32) -> Result<(), Error> {
+fn parse_command<D: EventHandler>(reader: &mut &[u8], dispatcher: &mut D, command: i32, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
     if let Some(command) = NET_Messages::from_i32(command) {
-        parse_net_command(reader, dispatcher, command)
+        // net_Tick keeps ParserContext::server_tick up to date.
+        if !dispatcher.wants(&MessageKind::Net(command)) && command != NET_Messages::net_Tick {
+            return Ok(Control::Continue);
+        }
+        parse_net_command(reader, dispatcher, command, state)
     } else if let Some(command) = SVC_Messages::from_i32(command) {
-        parse_svc_command(reader, dispatcher, command)
+        if !dispatcher.wants(&MessageKind::Svc(command)) && !needs_svc_command(command, options) {
+            return Ok(Control::Continue);
+        }
+        parse_svc_command(reader, dispatcher, command, options, state)
     } else {
-        Err(format!("Invalid Command {}", command).into())
+        Err(DemoError::unknown_command(command))
+    }
+}
+
+// Takes over from the dispatcher once a handler skipped the rest of a packet. Only the messages
+// the parser state depends on are still decoded, since entities and string tables are deltas.
+struct SkippedPacket;
+
+impl EventHandler for SkippedPacket {
+    fn wants(&self, _: &MessageKind) -> bool {
+        false
     }
 }
 
-fn parse_packet<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &D) -> Result<(), Error> {
+fn parse_packet<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
     let packet_info = PacketInfo::parse(reader)?;
-    dispatcher.dispatch(&packet_info)?;
-    let data_header = DataHeader::parse(reader)?;
+    let mut control = dispatcher.dispatch(&packet_info, &state.context)?;
 
-    let mut packet_size = data_header.size as usize;
-    let reader = &mut reader.take(data_header.size as u64);
+    let data = read_data(reader, options)?;
+    let reader = &mut &data[..];
 
-    while packet_size > 0 {
-        let command = read_varuint_and_dec!(reader, packet_size) as i32;
-        let size = read_varuint_and_dec!(reader, packet_size) as usize;
-        let reader = &mut reader.take(size as u64);
+    while !reader.is_empty() && control != Control::Stop {
+        let command = read_varuint(reader)?.0 as i32;
+        let size = read_varuint(reader)?.0 as usize;
+        let message = &mut split_data(reader, size)?;
 
-        parse_command(reader, dispatcher, command)?;
-        packet_size -= size;
+        if control == Control::Continue {
+            control = parse_command(message, dispatcher, command, options, state)?;
+        } else {
+            parse_command(message, &mut SkippedPacket, command, options, state)?;
+        }
     }
 
-    assert_eq!(reader.read(&mut [0u8; 1])?, 0);
-    Ok(())
+    Ok(control)
 }
 
-fn parse_datatables<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &D) -> Result<(), Error> {
-    let data_header = DataHeader::parse(reader)?;
-    let mut data_size = data_header.size as usize;
+fn parse_datatables<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    let data = read_data(reader, options)?;
+    let reader = &mut &data[..];
 
-    let reader = &mut reader.take(data_size as u64);
+    let mut registry = ServerClassRegistry::new();
+    let mut control = Control::Continue;
 
-    while data_size > 0 {
-        let _datatable_type = read_varuint_and_dec!(reader, data_size);
-        let datatable_size = read_varuint_and_dec!(reader, data_size) as usize;
+    while !reader.is_empty() {
+        let _datatable_type = read_varuint(reader)?.0;
+        let datatable_size = read_varuint(reader)?.0 as usize;
 
-        let message = CSVCMsg_SendTable::parse_from_reader(&mut reader.take(datatable_size as u64))?;
-        dispatcher.dispatch(&message)?;
-
-        data_size -= datatable_size;
+        let message = CSVCMsg_SendTable::parse_from_bytes(split_data(reader, datatable_size)?)?;
+        control = control.max(dispatcher.dispatch(&message, &state.context)?);
+        registry.add_send_table(&message);
 
         if message.get_is_end() {
             break;
         }
     }
 
-    let reader = &mut reader.take(data_size as u64);
-    
+
     // Valve, why?
     let server_classes = reader.read_u16_le()?;
     for _ in 0..server_classes {
@@ -176,54 +410,267 @@ fn parse_datatables<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher
             datatable
         };
 
-        dispatcher.dispatch(&server_class)?;
+        control = control.max(dispatcher.dispatch(&server_class, &state.context)?);
+        registry.add_server_class(server_class);
     }
 
-    assert_eq!(reader.read(&mut [0u8; 1])?, 0);
-    Ok(())
+    expect_consumed(reader)?;
+
+    registry.flatten()?;
+    control = control.max(dispatcher.dispatch(&registry, &state.context)?);
+
+    // Entities decoded against the previous tables would no longer line up.
+    state.entities.clear();
+    state.registry = Some(registry);
+    Ok(control)
 }
 
-pub fn parse_dem_file<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &D) -> Result<(), Error> {
-    let header = DemHeader::parse(reader)?;
-    dispatcher.dispatch(&header)?;
+fn parse_console_cmd<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, command_header: &CommandHeader, options: &ParseOptions, context: &ParserContext) -> Result<Control, Error> {
+    let mut data = read_data(reader, options)?;
 
-    loop {
-        let command_header = CommandHeader::parse(reader)?;
+    // The command is stored with its null terminator.
+    let length = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+    data.truncate(length);
 
-        match command_header.command {
-            // dem_signon | dem_packet
-            1 | 2 => parse_packet(reader, dispatcher)?,
+    let console_command = ConsoleCommand {
+        tick: command_header.tick,
+        player_slot: command_header.player_slot,
+        command: String::from_utf8(data)?
+    };
 
-            // dem_synctick
-            3 => {},
+    dispatcher.dispatch(&console_command, context)
+}
 
-            // dem_consolecmd
-            4 => unimplemented!(),
+// Mirrors ReadUsercmd. Demos delta each command against a zeroed one, so omitted fields are
+// zero, apart from the command number and tick count which are one more than the zeroed ones.
+fn read_user_cmd(reader: &mut BitReader) -> Result<UserCmd, Error> {
+    let mut user_cmd = UserCmd {
+        command_number: if reader.read_bit()? { reader.read_u32()? as i32 } else { 1 },
+        tick_count: if reader.read_bit()? { reader.read_u32()? as i32 } else { 1 },
+        ..UserCmd::default()
+    };
 
-            // dem_usercmd
-            5 => unimplemented!(),
+    for angle in user_cmd.view_angles.iter_mut() {
+        if reader.read_bit()? { *angle = reader.read_f32()?; }
+    }
+    for direction in user_cmd.aim_direction.iter_mut() {
+        if reader.read_bit()? { *direction = reader.read_f32()?; }
+    }
 
-            // dem_datatables
-            6 => parse_datatables(reader, dispatcher)?,
+    if reader.read_bit()? { user_cmd.forward_move = reader.read_f32()?; }
+    if reader.read_bit()? { user_cmd.side_move = reader.read_f32()?; }
+    if reader.read_bit()? { user_cmd.up_move = reader.read_f32()?; }
 
-            // dem_stop
-            7 => {
-                assert_eq!(reader.read(&mut [0u8; 1])?, 0);
-                break;
-            },
+    if reader.read_bit()? { user_cmd.buttons = reader.read_u32()?; }
+    if reader.read_bit()? { user_cmd.impulse = reader.read_u8()?; }
 
-            // dem_customdata
-            8 => unimplemented!(),
+    if reader.read_bit()? {
+        // MAX_EDICT_BITS and WEAPON_SUBTYPE_BITS
+        user_cmd.weapon_select = reader.read_bits(11)?;
+        if reader.read_bit()? { user_cmd.weapon_subtype = reader.read_bits(6)?; }
+    }
 
-            // dem_stringtables
-            9 => unimplemented!(),
+    if reader.read_bit()? { user_cmd.mouse_dx = reader.read_i16()?; }
+    if reader.read_bit()? { user_cmd.mouse_dy = reader.read_i16()?; }
 
-            command => {
-                eprintln!("Unknown command: {}", command);
-                break;
+    Ok(user_cmd)
+}
+
+fn parse_user_cmd<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, command_header: &CommandHeader, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    let sequence = reader.read_i32_le()?;
+    let data = read_data(reader, options)?;
+
+    let mut user_cmd = read_user_cmd(&mut BitReader::new(&data))?;
+    user_cmd.tick = command_header.tick;
+    user_cmd.player_slot = command_header.player_slot;
+    user_cmd.sequence = sequence;
+
+    dispatcher.dispatch(&user_cmd, &state.context)
+}
+
+fn parse_custom_data<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, command_header: &CommandHeader, options: &ParseOptions, context: &ParserContext) -> Result<Control, Error> {
+    let callback_index = reader.read_i32_le()?;
+    let data = read_data(reader, options)?;
+
+    let custom_data = CustomData {
+        tick: command_header.tick,
+        callback_index,
+        data
+    };
+
+    dispatcher.dispatch(&custom_data, context)
+}
+
+fn parse_string_table_entries(reader: &mut BitReader) -> Result<Vec<StringTableEntry>, Error> {
+    let entry_count = reader.read_u16()?;
+    let mut entries = Vec::with_capacity(entry_count as usize);
+
+    for _ in 0..entry_count {
+        let name = reader.read_string()?;
+        let user_data = if reader.read_bit()? {
+            let size = reader.read_u16()? as usize;
+            Some(reader.read_bytes(size)?)
+        } else {
+            None
+        };
+
+        entries.push(StringTableEntry {
+            name,
+            user_data
+        });
+    }
+
+    Ok(entries)
+}
+
+fn parse_string_tables<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    let data = read_data(reader, options)?;
+
+    let reader = &mut BitReader::new(&data);
+
+    let table_count = reader.read_u8()?;
+    let mut tables = Vec::with_capacity(table_count as usize);
+    for _ in 0..table_count {
+        let name = reader.read_string()?;
+        let entries = parse_string_table_entries(reader)?;
+        let client_entries = if reader.read_bit()? {
+            parse_string_table_entries(reader)?
+        } else {
+            Vec::new()
+        };
+
+        tables.push(StringTableSnapshot {
+            name,
+            entries,
+            client_entries
+        });
+    }
+
+    let snapshot = StringTablesSnapshot { tables };
+    let mut control = dispatcher.dispatch(&snapshot, &state.context)?;
+
+    if options.decodes_string_tables() {
+        let (roster, entities, context) = (&mut state.roster, &mut state.entities, &state.context);
+        state.string_tables.apply_snapshot(&snapshot, |table, index| {
+            control = control.max(on_string_table_entry(dispatcher, roster, entities, context, table, index)?);
+            Ok(())
+        })?;
+    }
+
+    Ok(control)
+}
+
+fn read_command_header<R: Read + Sized>(reader: &mut R, context: &mut ErrorContext) -> Result<CommandHeader, Error> {
+    let command_header = CommandHeader::parse(reader)?;
+    context.tick = command_header.tick;
+    context.command = command_header.command;
+    Ok(command_header)
+}
+
+fn parse_frame<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, command_header: &CommandHeader, options: &ParseOptions, state: &mut ParserState) -> Result<Control, Error> {
+    // SkipPacket has no packet to skip here, only Stop is honoured.
+    if state.begin_frame(command_header) && dispatcher.dispatch(&TickStart { tick: command_header.tick }, &state.context)? == Control::Stop {
+        return Ok(Control::Stop);
+    }
+
+    let control = match command_header.command {
+        // dem_signon | dem_packet
+        1 | 2 => parse_packet(reader, dispatcher, options, state)?,
+
+        // dem_synctick
+        3 => Control::Continue,
+
+        // dem_consolecmd
+        4 => parse_console_cmd(reader, dispatcher, command_header, options, &state.context)?,
+
+        // dem_usercmd
+        5 => parse_user_cmd(reader, dispatcher, command_header, options, state)?,
+
+        // dem_datatables
+        6 => parse_datatables(reader, dispatcher, options, state)?,
+
+        // dem_stop
+        7 => {
+            let trailing = reader.read(&mut [0u8; 1])?;
+            if trailing != 0 {
+                return Err(DemoError::size_mismatch(0, trailing as u64));
+            }
+            end_tick(dispatcher, state)?;
+            Control::Stop
+        },
+
+        // dem_customdata
+        8 => parse_custom_data(reader, dispatcher, command_header, options, &state.context)?,
+
+        // dem_stringtables
+        9 => parse_string_tables(reader, dispatcher, options, state)?,
+
+        command => return Err(DemoError::unknown_command(command as i32))
+    };
+
+    Ok(control)
+}
+
+// Only running out of demo input counts as a truncated demo. Frame data that was read in full
+// but ends early is corrupt.
+fn is_truncated_demo<R: Read>(error: &Error, reader: &CountingReader<R>, options: &ParseOptions) -> bool {
+    options.lenient && reader.eof() && matches!(error, DemoError::Truncated { .. })
+}
+
+// Ends the tick the demo was cut off in, so every TickStart is matched by a TickEnd.
+fn end_truncated_demo<D: EventHandler>(dispatcher: &mut D, state: &ParserState, last_tick: i32) -> Result<(), Error> {
+    if state.frames > 0 {
+        end_tick(dispatcher, state)?;
+    }
+    dispatcher.dispatch(&TruncatedDemo { last_tick }, &state.context)?;
+    Ok(())
+}
+
+fn parse_frames<R: Read, D: EventHandler>(reader: &mut CountingReader<R>, dispatcher: &mut D, options: &ParseOptions, context: &mut ErrorContext) -> Result<(), Error> {
+    let mut state = ParserState::new(options);
+
+    let header = DemHeader::parse(reader)?;
+    if dispatcher.dispatch(&header, &state.context)? == Control::Stop {
+        return Ok(());
+    }
+    let mut last_tick = 0;
+
+    loop {
+        let frame = read_command_header(reader, context).and_then(|command_header| {
+            if state.ends_tick(&command_header) && end_tick(dispatcher, &state)? == Control::Stop {
+                return Ok(Control::Stop);
             }
+            parse_frame(reader, dispatcher, &command_header, options, &mut state)
+        });
+
+        match frame {
+            Ok(Control::Stop) => break,
+            Ok(_) => last_tick = context.tick,
+
+            Err(error) if is_truncated_demo(&error, reader, options) => {
+                end_truncated_demo(dispatcher, &state, last_tick)?;
+                break;
+            },
+
+            Err(error) => return Err(error)
         }
     }
 
     Ok(())
 }
+
+pub fn parse_dem_file<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D) -> Result<(), Error> {
+    parse_dem_file_with_options(reader, dispatcher, &ParseOptions::default())
+}
+
+pub fn parse_dem_file_with_options<R: Read + Sized, D: EventHandler>(reader: &mut R, dispatcher: &mut D, options: &ParseOptions) -> Result<(), Error> {
+    let reader = &mut CountingReader::new(reader);
+    let mut context = ErrorContext::default();
+
+    parse_frames(reader, dispatcher, options, &mut context).map_err(|error| {
+        error.with_context(ErrorContext {
+            offset: reader.position(),
+            ..context
+        })
+    })
+}
@@ -0,0 +1,620 @@
+use std::collections::VecDeque;
+use std::io::Read;
+
+use crate::entities::EntityTable;
+use crate::events::*;
+use crate::players::PlayerRoster;
+use crate::stringtables::StringTables;
+use crate::util::CountingReader;
+use crate::{end_tick, end_truncated_demo, is_truncated_demo, parse_frame, read_command_header, Error, ErrorContext, ParseOptions, ParserState};
+
+macro_rules! owned_messages {
+    ($name:ident { $($variant:ident($ty:ty)),+ } $({ $($extra:tt)* })?) => {
+        #[derive(Clone, Debug)]
+        #[allow(clippy::large_enum_variant)]
+        pub enum $name {
+            $($variant($ty),)+
+            $($($extra)*)?
+        }
+
+        $(
+            impl From<$ty> for $name {
+                fn from(message: $ty) -> Self {
+                    $name::$variant(message)
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! queue_events {
+    ($owned:ident; $($ident:ident => $ty:ty);+) => ($(
+        fn $ident(&mut self, event: &$ty, context: &ParserContext) -> Result<Control, Error> {
+            self.push(context, $owned::from(event.clone()))
+        }
+    )+);
+}
+
+owned_messages! {
+    DemoEventKind {
+        Header(DemHeader),
+        PacketInfo(PacketInfo),
+        ServerClass(ServerClass),
+        StringTables(StringTablesSnapshot),
+        ConsoleCommand(ConsoleCommand),
+        UserCmd(UserCmd),
+        CustomData(CustomData),
+        Truncated(TruncatedDemo),
+        TickStart(TickStart),
+        Net(NetMessage),
+        Svc(SvcMessage),
+        UserMessage(UserMessage)
+    }
+    {
+        // The parser state is left at the end of the tick until the next event is read.
+        TickEnd { tick: i32 }
+    }
+}
+
+owned_messages! {
+    NetMessage {
+        Nop(CNETMsg_NOP),
+        Disconnect(CNETMsg_Disconnect),
+        File(CNETMsg_File),
+        SplitScreenUser(CNETMsg_SplitScreenUser),
+        Tick(CNETMsg_Tick),
+        StringCmd(CNETMsg_StringCmd),
+        SetConVar(CNETMsg_SetConVar),
+        SignonState(CNETMsg_SignonState),
+        PlayerAvatarData(CNETMsg_PlayerAvatarData)
+    }
+}
+
+// svc_UserMessage is decoded and shows up as DemoEventKind::UserMessage instead.
+owned_messages! {
+    SvcMessage {
+        ServerInfo(CSVCMsg_ServerInfo),
+        SendTable(CSVCMsg_SendTable),
+        ClassInfo(CSVCMsg_ClassInfo),
+        SetPause(CSVCMsg_SetPause),
+        CreateStringTable(CSVCMsg_CreateStringTable),
+        UpdateStringTable(CSVCMsg_UpdateStringTable),
+        VoiceInit(CSVCMsg_VoiceInit),
+        VoiceData(CSVCMsg_VoiceData),
+        Print(CSVCMsg_Print),
+        Sounds(CSVCMsg_Sounds),
+        SetView(CSVCMsg_SetView),
+        FixAngle(CSVCMsg_FixAngle),
+        CrosshairAngle(CSVCMsg_CrosshairAngle),
+        BSPDecal(CSVCMsg_BSPDecal),
+        SplitScreen(CSVCMsg_SplitScreen),
+        EntityMsg(CSVCMsg_EntityMsg),
+        GameEvent(CSVCMsg_GameEvent),
+        PacketEntities(CSVCMsg_PacketEntities),
+        TempEntities(CSVCMsg_TempEntities),
+        Prefetch(CSVCMsg_Prefetch),
+        Menu(CSVCMsg_Menu),
+        GameEventList(CSVCMsg_GameEventList),
+        GetCvarValue(CSVCMsg_GetCvarValue),
+        PaintmapData(CSVCMsg_PaintmapData),
+        CmdKeyValues(CSVCMsg_CmdKeyValues),
+        EncryptedData(CSVCMsg_EncryptedData),
+        HltvReplay(CSVCMsg_HltvReplay),
+        BroadcastCommand(CSVCMsg_Broadcast_Command)
+    }
+}
+
+owned_messages! {
+    UserMessage {
+        VGUIMenu(CCSUsrMsg_VGUIMenu),
+        Geiger(CCSUsrMsg_Geiger),
+        Train(CCSUsrMsg_Train),
+        HudText(CCSUsrMsg_HudText),
+        SayText(CCSUsrMsg_SayText),
+        SayText2(CCSUsrMsg_SayText2),
+        TextMsg(CCSUsrMsg_TextMsg),
+        HudMsg(CCSUsrMsg_HudMsg),
+        ResetHud(CCSUsrMsg_ResetHud),
+        GameTitle(CCSUsrMsg_GameTitle),
+        Shake(CCSUsrMsg_Shake),
+        Fade(CCSUsrMsg_Fade),
+        Rumble(CCSUsrMsg_Rumble),
+        CloseCaption(CCSUsrMsg_CloseCaption),
+        CloseCaptionDirect(CCSUsrMsg_CloseCaptionDirect),
+        SendAudio(CCSUsrMsg_SendAudio),
+        RawAudio(CCSUsrMsg_RawAudio),
+        VoiceMask(CCSUsrMsg_VoiceMask),
+        RequestState(CCSUsrMsg_RequestState),
+        Damage(CCSUsrMsg_Damage),
+        RadioText(CCSUsrMsg_RadioText),
+        HintText(CCSUsrMsg_HintText),
+        KeyHintText(CCSUsrMsg_KeyHintText),
+        ProcessSpottedEntityUpdate(CCSUsrMsg_ProcessSpottedEntityUpdate),
+        ReloadEffect(CCSUsrMsg_ReloadEffect),
+        AdjustMoney(CCSUsrMsg_AdjustMoney),
+        StopSpectatorMode(CCSUsrMsg_StopSpectatorMode),
+        KillCam(CCSUsrMsg_KillCam),
+        DesiredTimescale(CCSUsrMsg_DesiredTimescale),
+        CurrentTimescale(CCSUsrMsg_CurrentTimescale),
+        AchievementEvent(CCSUsrMsg_AchievementEvent),
+        MatchEndConditions(CCSUsrMsg_MatchEndConditions),
+        DisconnectToLobby(CCSUsrMsg_DisconnectToLobby),
+        PlayerStatsUpdate(CCSUsrMsg_PlayerStatsUpdate),
+        DisplayInventory(CCSUsrMsg_DisplayInventory),
+        WarmupHasEnded(CCSUsrMsg_WarmupHasEnded),
+        ClientInfo(CCSUsrMsg_ClientInfo),
+        XRankGet(CCSUsrMsg_XRankGet),
+        XRankUpd(CCSUsrMsg_XRankUpd),
+        CallVoteFailed(CCSUsrMsg_CallVoteFailed),
+        VoteStart(CCSUsrMsg_VoteStart),
+        VotePass(CCSUsrMsg_VotePass),
+        VoteFailed(CCSUsrMsg_VoteFailed),
+        VoteSetup(CCSUsrMsg_VoteSetup),
+        ServerRankRevealAll(CCSUsrMsg_ServerRankRevealAll),
+        SendLastKillerDamageToClient(CCSUsrMsg_SendLastKillerDamageToClient),
+        ServerRankUpdate(CCSUsrMsg_ServerRankUpdate),
+        ItemPickup(CCSUsrMsg_ItemPickup),
+        ShowMenu(CCSUsrMsg_ShowMenu),
+        BarTime(CCSUsrMsg_BarTime),
+        AmmoDenied(CCSUsrMsg_AmmoDenied),
+        MarkAchievement(CCSUsrMsg_MarkAchievement),
+        MatchStatsUpdate(CCSUsrMsg_MatchStatsUpdate),
+        ItemDrop(CCSUsrMsg_ItemDrop),
+        GlowPropTurnOff(CCSUsrMsg_GlowPropTurnOff),
+        SendPlayerItemDrops(CCSUsrMsg_SendPlayerItemDrops),
+        RoundBackupFilenames(CCSUsrMsg_RoundBackupFilenames),
+        SendPlayerItemFound(CCSUsrMsg_SendPlayerItemFound),
+        ReportHit(CCSUsrMsg_ReportHit),
+        XpUpdate(CCSUsrMsg_XpUpdate),
+        QuestProgress(CCSUsrMsg_QuestProgress),
+        ScoreLeaderboardData(CCSUsrMsg_ScoreLeaderboardData),
+        PlayerDecalDigitalSignature(CCSUsrMsg_PlayerDecalDigitalSignature),
+        WeaponSound(CCSUsrMsg_WeaponSound),
+        UpdateScreenHealthBar(CCSUsrMsg_UpdateScreenHealthBar),
+        EntityOutlineHighlight(CCSUsrMsg_EntityOutlineHighlight),
+        Ssui(CCSUsrMsg_SSUI),
+        SurvivalStats(CCSUsrMsg_SurvivalStats),
+        EndOfMatchAllPlayersData(CCSUsrMsg_EndOfMatchAllPlayersData),
+        RoundImpactScoreData(CCSUsrMsg_RoundImpactScoreData),
+        CurrentRoundOdds(CCSUsrMsg_CurrentRoundOdds),
+        DeepStats(CCSUsrMsg_DeepStats)
+    }
+    {
+        // Carries the same message as DisconnectToLobby.
+        DisconnectToLobby2(CCSUsrMsg_DisconnectToLobby),
+        UpdateTeamMoney(Vec<u8>),
+        Unknown { msg_type: i32, data: Vec<u8> }
+    }
+}
+
+// Events derived from the parser state (string table entries, roster changes and
+// entity updates) are not part of the stream, the state itself is available from
+// the DemoParser instead.
+#[derive(Clone, Debug)]
+pub struct DemoEvent {
+    // Where the parser was when the event was read, all zero for the header.
+    pub context: ParserContext,
+    pub kind: DemoEventKind
+}
+
+#[derive(Default)]
+struct EventQueue(VecDeque<DemoEvent>);
+
+impl EventQueue {
+    fn push<E: Into<DemoEventKind>>(&mut self, context: &ParserContext, event: E) -> Result<Control, Error> {
+        self.0.push_back(DemoEvent { context: *context, kind: event.into() });
+        Ok(Control::Continue)
+    }
+}
+
+impl EventHandler for EventQueue {
+    queue_events! {
+        DemoEventKind;
+
+        on_dem_header => DemHeader;
+        on_packet_info => PacketInfo;
+        on_server_class => ServerClass;
+        on_string_tables => StringTablesSnapshot;
+        on_console_cmd => ConsoleCommand;
+        on_user_cmd => UserCmd;
+        on_custom_data => CustomData;
+        on_truncated => TruncatedDemo;
+        on_tick_start => TickStart
+    }
+
+    fn on_tick_end(&mut self, event: &TickEnd, context: &ParserContext) -> Result<Control, Error> {
+        self.push(context, DemoEventKind::TickEnd { tick: event.tick })
+    }
+
+    queue_events! {
+        NetMessage;
+
+        on_nop => CNETMsg_NOP;
+        on_disconnect => CNETMsg_Disconnect;
+        on_file => CNETMsg_File;
+        on_split_screen_user => CNETMsg_SplitScreenUser;
+        on_tick => CNETMsg_Tick;
+        on_string_cmd => CNETMsg_StringCmd;
+        on_set_con_var => CNETMsg_SetConVar;
+        on_signon_state => CNETMsg_SignonState;
+        on_player_avatar_data => CNETMsg_PlayerAvatarData
+    }
+
+    queue_events! {
+        SvcMessage;
+
+        on_server_info => CSVCMsg_ServerInfo;
+        on_send_table => CSVCMsg_SendTable;
+        on_class_info => CSVCMsg_ClassInfo;
+        on_set_pause => CSVCMsg_SetPause;
+        on_create_string_table => CSVCMsg_CreateStringTable;
+        on_update_string_table => CSVCMsg_UpdateStringTable;
+        on_voice_init => CSVCMsg_VoiceInit;
+        on_voice_data => CSVCMsg_VoiceData;
+        on_print => CSVCMsg_Print;
+        on_sounds => CSVCMsg_Sounds;
+        on_set_view => CSVCMsg_SetView;
+        on_fix_angle => CSVCMsg_FixAngle;
+        on_crosshair_angle => CSVCMsg_CrosshairAngle;
+        on_bspdecal => CSVCMsg_BSPDecal;
+        on_split_screen => CSVCMsg_SplitScreen;
+        on_entity_message => CSVCMsg_EntityMsg;
+        on_game_event => CSVCMsg_GameEvent;
+        on_packet_entities => CSVCMsg_PacketEntities;
+        on_temp_entities => CSVCMsg_TempEntities;
+        on_prefetch => CSVCMsg_Prefetch;
+        on_menu => CSVCMsg_Menu;
+        on_game_event_list => CSVCMsg_GameEventList;
+        on_get_cvar_value => CSVCMsg_GetCvarValue;
+        on_paintmap_data => CSVCMsg_PaintmapData;
+        on_cmd_key_values => CSVCMsg_CmdKeyValues;
+        on_encrypted_data => CSVCMsg_EncryptedData;
+        on_hltv_replay => CSVCMsg_HltvReplay;
+        on_broadcast_command => CSVCMsg_Broadcast_Command
+    }
+}
+
+impl UserMessageEventHandler for EventQueue {
+    queue_events! {
+        UserMessage;
+
+        on_vguimenu => CCSUsrMsg_VGUIMenu;
+        on_geiger => CCSUsrMsg_Geiger;
+        on_train => CCSUsrMsg_Train;
+        on_hud_text => CCSUsrMsg_HudText;
+        on_say_text => CCSUsrMsg_SayText;
+        on_say_text2 => CCSUsrMsg_SayText2;
+        on_text_msg => CCSUsrMsg_TextMsg;
+        on_hud_msg => CCSUsrMsg_HudMsg;
+        on_reset_hud => CCSUsrMsg_ResetHud;
+        on_game_title => CCSUsrMsg_GameTitle;
+        on_shake => CCSUsrMsg_Shake;
+        on_fade => CCSUsrMsg_Fade;
+        on_rumble => CCSUsrMsg_Rumble;
+        on_close_caption => CCSUsrMsg_CloseCaption;
+        on_close_caption_direct => CCSUsrMsg_CloseCaptionDirect;
+        on_send_audio => CCSUsrMsg_SendAudio;
+        on_raw_audio => CCSUsrMsg_RawAudio;
+        on_voice_mask => CCSUsrMsg_VoiceMask;
+        on_request_state => CCSUsrMsg_RequestState;
+        on_damage => CCSUsrMsg_Damage;
+        on_radio_text => CCSUsrMsg_RadioText;
+        on_hint_text => CCSUsrMsg_HintText;
+        on_key_hint_text => CCSUsrMsg_KeyHintText;
+        on_process_spotted_entity_update => CCSUsrMsg_ProcessSpottedEntityUpdate;
+        on_reload_effect => CCSUsrMsg_ReloadEffect;
+        on_adjust_money => CCSUsrMsg_AdjustMoney;
+        on_stop_spectator_mode => CCSUsrMsg_StopSpectatorMode;
+        on_kill_cam => CCSUsrMsg_KillCam;
+        on_desired_timescale => CCSUsrMsg_DesiredTimescale;
+        on_current_timescale => CCSUsrMsg_CurrentTimescale;
+        on_achievement_event => CCSUsrMsg_AchievementEvent;
+        on_match_end_conditions => CCSUsrMsg_MatchEndConditions;
+        on_disconnect_to_lobby => CCSUsrMsg_DisconnectToLobby;
+        on_player_stats_update => CCSUsrMsg_PlayerStatsUpdate;
+        on_display_inventory => CCSUsrMsg_DisplayInventory;
+        on_warmup_has_ended => CCSUsrMsg_WarmupHasEnded;
+        on_client_info => CCSUsrMsg_ClientInfo;
+        on_xrank_get => CCSUsrMsg_XRankGet;
+        on_xrank_upd => CCSUsrMsg_XRankUpd;
+        on_call_vote_failed => CCSUsrMsg_CallVoteFailed;
+        on_vote_start => CCSUsrMsg_VoteStart;
+        on_vote_pass => CCSUsrMsg_VotePass;
+        on_vote_failed => CCSUsrMsg_VoteFailed;
+        on_vote_setup => CCSUsrMsg_VoteSetup;
+        on_server_rank_reveal_all => CCSUsrMsg_ServerRankRevealAll;
+        on_send_last_killer_damage_to_client => CCSUsrMsg_SendLastKillerDamageToClient;
+        on_server_rank_update => CCSUsrMsg_ServerRankUpdate;
+        on_item_pickup => CCSUsrMsg_ItemPickup;
+        on_show_menu => CCSUsrMsg_ShowMenu;
+        on_bar_time => CCSUsrMsg_BarTime;
+        on_ammo_denied => CCSUsrMsg_AmmoDenied;
+        on_mark_achievement => CCSUsrMsg_MarkAchievement;
+        on_match_stats_update => CCSUsrMsg_MatchStatsUpdate;
+        on_item_drop => CCSUsrMsg_ItemDrop;
+        on_glow_prop_turn_off => CCSUsrMsg_GlowPropTurnOff;
+        on_send_player_item_drops => CCSUsrMsg_SendPlayerItemDrops;
+        on_round_backup_filenames => CCSUsrMsg_RoundBackupFilenames;
+        on_send_player_item_found => CCSUsrMsg_SendPlayerItemFound;
+        on_report_hit => CCSUsrMsg_ReportHit;
+        on_xp_update => CCSUsrMsg_XpUpdate;
+        on_quest_progress => CCSUsrMsg_QuestProgress;
+        on_score_leaderboard_data => CCSUsrMsg_ScoreLeaderboardData;
+        on_player_decal_digital_signature => CCSUsrMsg_PlayerDecalDigitalSignature;
+        on_weapon_sound => CCSUsrMsg_WeaponSound;
+        on_update_screen_health_bar => CCSUsrMsg_UpdateScreenHealthBar;
+        on_entity_outline_highlight => CCSUsrMsg_EntityOutlineHighlight;
+        on_ssui => CCSUsrMsg_SSUI;
+        on_survival_stats => CCSUsrMsg_SurvivalStats;
+        on_end_of_match_all_players_data => CCSUsrMsg_EndOfMatchAllPlayersData;
+        on_round_impact_score_data => CCSUsrMsg_RoundImpactScoreData;
+        on_current_round_odds => CCSUsrMsg_CurrentRoundOdds;
+        on_deep_stats => CCSUsrMsg_DeepStats
+    }
+
+    fn on_disconnect_to_lobby2(&mut self, event: &CCSUsrMsg_DisconnectToLobby, context: &ParserContext) -> Result<Control, Error> {
+        self.push(context, UserMessage::DisconnectToLobby2(event.clone()))
+    }
+
+    fn on_update_team_money(&mut self, event: &UpdateTeamMoney, context: &ParserContext) -> Result<Control, Error> {
+        self.push(context, UserMessage::UpdateTeamMoney(event.data.to_vec()))
+    }
+
+    fn on_unknown_user_message(&mut self, event: &UnknownUserMessage, context: &ParserContext) -> Result<Control, Error> {
+        self.push(context, UserMessage::Unknown { msg_type: event.msg_type, data: event.data.to_vec() })
+    }
+}
+
+// Pull based alternative to parse_dem_file: frames are only read once the events
+// of the previous one have been consumed, so dropping the parser stops parsing.
+pub struct DemoParser<R> {
+    reader: CountingReader<R>,
+    options: ParseOptions,
+    context: ErrorContext,
+    state: ParserState,
+    events: UserMessageDecoder<EventQueue>,
+    // A frame of the next tick, read before the TickEnd of the current one was consumed.
+    pending: Option<CommandHeader>,
+    last_tick: i32,
+    started: bool,
+    finished: bool,
+    // Reported once the events read before it have been consumed.
+    error: Option<Error>
+}
+
+impl<R: Read> DemoParser<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ParseOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: ParseOptions) -> Self {
+        DemoParser {
+            reader: CountingReader::new(reader),
+            state: ParserState::new(&options),
+            options,
+            context: ErrorContext::default(),
+            events: UserMessageDecoder(EventQueue::default()),
+            pending: None,
+            last_tick: 0,
+            started: false,
+            finished: false,
+            error: None
+        }
+    }
+
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    // Describes the last frame read, which may be ahead of the events not yet consumed.
+    pub fn context(&self) -> &ParserContext {
+        &self.state.context
+    }
+
+    pub fn server_classes(&self) -> Option<&ServerClassRegistry> {
+        self.state.registry.as_ref()
+    }
+
+    pub fn string_tables(&self) -> &StringTables {
+        &self.state.string_tables
+    }
+
+    pub fn players(&self) -> &PlayerRoster {
+        &self.state.roster
+    }
+
+    pub fn entities(&self) -> &EntityTable {
+        &self.state.entities
+    }
+
+    pub fn next_event(&mut self) -> Result<Option<DemoEvent>, Error> {
+        loop {
+            if let Some(event) = self.events.0.0.pop_front() {
+                return Ok(Some(event));
+            }
+            if let Some(error) = self.error.take() {
+                return Err(error);
+            }
+            if self.finished {
+                return Ok(None);
+            }
+
+            if let Err(error) = self.parse_next() {
+                self.finished = true;
+                self.error = Some(error.with_context(ErrorContext {
+                    offset: self.reader.position(),
+                    ..self.context
+                }));
+            }
+        }
+    }
+
+    fn parse_next(&mut self) -> Result<(), Error> {
+        if !self.started {
+            self.started = true;
+
+            let header = DemHeader::parse(&mut self.reader)?;
+            self.events.dispatch(&header, &self.state.context)?;
+            return Ok(());
+        }
+
+        let frame = match self.pending.take() {
+            Some(command_header) => parse_frame(&mut self.reader, &mut self.events, &command_header, &self.options, &mut self.state),
+            None => read_command_header(&mut self.reader, &mut self.context).and_then(|command_header| {
+                if self.state.ends_tick(&command_header) {
+                    self.pending = Some(command_header);
+                    return end_tick(&mut self.events, &self.state);
+                }
+                parse_frame(&mut self.reader, &mut self.events, &command_header, &self.options, &mut self.state)
+            })
+        };
+
+        match frame {
+            Ok(Control::Stop) => self.finished = true,
+            Ok(_) => self.last_tick = self.state.context.tick,
+
+            Err(error) if is_truncated_demo(&error, &self.reader, &self.options) => {
+                self.finished = true;
+                end_truncated_demo(&mut self.events, &self.state, self.last_tick)?;
+            },
+
+            Err(error) => return Err(error)
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for DemoParser<R> {
+    type Item = Result<DemoEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protobuf::Message;
+
+    use super::*;
+    use crate::DemoError;
+
+    // Builds a demo one frame at a time, behind a blank header.
+    struct Demo(Vec<u8>);
+
+    impl Demo {
+        fn new() -> Self {
+            Demo(vec![0; std::mem::size_of::<DemHeader>()])
+        }
+
+        fn frame(mut self, command: u8, tick: i32) -> Self {
+            self.0.push(command);
+            self.0.extend(&tick.to_le_bytes());
+            self.0.push(0);
+            self
+        }
+
+        fn block(mut self, data: &[u8]) -> Self {
+            self.0.extend(&(data.len() as i32).to_le_bytes());
+            self.0.extend(data);
+            self
+        }
+
+        // A packet with a single net_Tick.
+        fn packet(mut self, tick: i32) -> Self {
+            let message = CNETMsg_Tick { tick: Some(tick as u32), ..CNETMsg_Tick::default() }.write_to_bytes().unwrap();
+            let mut data = vec![NET_Messages::net_Tick as u8, message.len() as u8];
+            data.extend(message);
+
+            self = self.frame(2, tick);
+            self.0.extend(vec![0; std::mem::size_of::<PacketInfo>()]);
+            self.block(&data)
+        }
+
+        fn console_cmd(self, tick: i32, command: &str) -> Self {
+            self.frame(4, tick).block(format!("{}\0", command).as_bytes())
+        }
+    }
+
+    fn label(event: &DemoEvent) -> String {
+        match &event.kind {
+            DemoEventKind::Header(_) => "header".to_owned(),
+            DemoEventKind::TickStart(event) => format!("tick_start {}", event.tick),
+            DemoEventKind::TickEnd { tick } => format!("tick_end {}", tick),
+            DemoEventKind::PacketInfo(_) => "packet_info".to_owned(),
+            DemoEventKind::Net(NetMessage::Tick(message)) => format!("net_tick {}", message.get_tick()),
+            DemoEventKind::ConsoleCommand(command) => format!("console_cmd {}", command.command),
+            DemoEventKind::Truncated(event) => format!("truncated {}", event.last_tick),
+            kind => format!("{:?}", kind)
+        }
+    }
+
+    // Every event up to and including the first error.
+    fn read_all(data: &[u8], options: ParseOptions) -> (Vec<String>, Option<Error>) {
+        let mut events = Vec::new();
+        for event in DemoParser::with_options(data, options) {
+            match event {
+                Ok(event) => events.push(label(&event)),
+                Err(error) => return (events, Some(error))
+            }
+        }
+        (events, None)
+    }
+
+    #[test]
+    fn reads_events_tick_by_tick() {
+        let demo = Demo::new().packet(1).frame(3, 1).console_cmd(2, "say hi").frame(7, 2);
+
+        let (events, error) = read_all(&demo.0, ParseOptions::default());
+        assert!(error.is_none());
+        assert_eq!(events, vec![
+            "header",
+            "tick_start 1",
+            "packet_info",
+            "net_tick 1",
+            // The console command was read to find the end of tick 1 and is kept for tick 2.
+            "tick_end 1",
+            "tick_start 2",
+            "console_cmd say hi",
+            "tick_end 2"
+        ]);
+    }
+
+    #[test]
+    fn stops_at_dem_stop() {
+        let demo = Demo::new().packet(1).frame(7, 1);
+
+        let mut parser = DemoParser::new(&demo.0[..]);
+        assert_eq!(parser.by_ref().map(|event| label(&event.unwrap())).last(), Some("tick_end 1".to_owned()));
+        assert!(parser.next().is_none());
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn reports_errors_after_the_events_read_before_them() {
+        let demo = Demo::new().packet(1).frame(42, 2);
+
+        let (events, error) = read_all(&demo.0, ParseOptions::default());
+        assert_eq!(events, vec!["header", "tick_start 1", "packet_info", "net_tick 1", "tick_end 1", "tick_start 2"]);
+        assert!(matches!(error, Some(DemoError::UnknownCommand { command: 42, .. })));
+
+        let mut parser = DemoParser::new(&demo.0[..]);
+        assert!(parser.by_ref().any(|event| event.is_err()));
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn ends_truncated_demos_when_lenient() {
+        let demo = Demo::new().packet(1).packet(2);
+        let data = &demo.0[..demo.0.len() - 3];
+
+        let (events, error) = read_all(data, ParseOptions { lenient: true, ..ParseOptions::default() });
+        assert!(error.is_none());
+        assert_eq!(&events[4..], &["tick_end 1", "tick_start 2", "packet_info", "tick_end 2", "truncated 1"]);
+
+        let (events, error) = read_all(data, ParseOptions::default());
+        assert_eq!(events.last().map(String::as_str), Some("packet_info"));
+        assert!(matches!(error, Some(DemoError::Truncated { .. })));
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::format::{Parse, PlayerInfo};
+use crate::Error;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Player {
+    // Entries of the userinfo table are stored at the player's entity index minus one.
+    pub entity_index: u32,
+    pub user_id: i32,
+    // Zero for bots and the GOTV proxy.
+    pub xuid: u64,
+    pub name: String,
+    pub guid: String,
+    pub friends_id: u32,
+    pub fake_player: bool,
+    pub is_hltv: bool
+}
+
+impl Player {
+    fn new(entity_index: u32, info: &PlayerInfo) -> Self {
+        Player {
+            entity_index,
+            user_id: info.user_id,
+            xuid: info.xuid,
+            name: info.name(),
+            guid: info.guid(),
+            friends_id: info.friends_id,
+            fake_player: info.fake_player,
+            is_hltv: info.is_hltv
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RosterChangeKind {
+    Joined,
+    Renamed,
+    Left
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RosterChange<'a> {
+    pub kind: RosterChangeKind,
+    pub player: &'a Player,
+    // Only set for renames.
+    pub previous_name: Option<&'a str>
+}
+
+// Players are stored by user id, which is what game events refer to.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerRoster {
+    players: HashMap<i32, Player>,
+    entity_indices: HashMap<u32, i32>,
+    xuids: HashMap<u64, i32>
+}
+
+impl PlayerRoster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn by_user_id(&self, user_id: i32) -> Option<&Player> {
+        self.players.get(&user_id)
+    }
+
+    pub fn by_entity_index(&self, entity_index: u32) -> Option<&Player> {
+        self.entity_indices.get(&entity_index).and_then(|user_id| self.players.get(user_id))
+    }
+
+    pub fn by_xuid(&self, xuid: u64) -> Option<&Player> {
+        self.xuids.get(&xuid).and_then(|user_id| self.players.get(user_id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Player> {
+        self.players.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    // Applies an entry of the userinfo string table. Missing or empty user data, which is
+    // what the server sends when a player disconnects, frees the slot.
+    pub(crate) fn update<F>(&mut self, table_index: usize, user_data: Option<&[u8]>, mut on_change: F) -> Result<(), Error>
+    where
+        F: FnMut(&RosterChange) -> Result<(), Error>
+    {
+        let entity_index = table_index as u32 + 1;
+
+        let player = match user_data {
+            Some(mut data) if !data.is_empty() => Some(Player::new(entity_index, &PlayerInfo::parse(&mut data)?)),
+            _ => None
+        };
+
+        let previous = self.entity_indices.get(&entity_index).copied();
+        match (previous, player) {
+            (Some(user_id), Some(player)) if user_id == player.user_id => {
+                let previous_name = self.players.get(&user_id).map(|previous| previous.name.clone());
+                let player = self.insert(player);
+
+                if let Some(previous_name) = previous_name.filter(|name| *name != player.name) {
+                    on_change(&RosterChange { kind: RosterChangeKind::Renamed, player, previous_name: Some(&previous_name) })?;
+                }
+            },
+            (previous, player) => {
+                if let Some(previous) = previous.and_then(|user_id| self.remove(user_id)) {
+                    on_change(&RosterChange { kind: RosterChangeKind::Left, player: &previous, previous_name: None })?;
+                }
+                if let Some(player) = player {
+                    let player = self.insert(player);
+                    on_change(&RosterChange { kind: RosterChangeKind::Joined, player, previous_name: None })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, player: Player) -> &Player {
+        let user_id = player.user_id;
+
+        // A reconnecting user id may have been left behind in another slot.
+        if let Some(stale) = self.players.get(&user_id).map(|stale| stale.entity_index) {
+            if stale != player.entity_index {
+                self.entity_indices.remove(&stale);
+            }
+        }
+
+        self.entity_indices.insert(player.entity_index, user_id);
+        if player.xuid != 0 {
+            self.xuids.insert(player.xuid, user_id);
+        }
+
+        self.players.insert(user_id, player);
+        &self.players[&user_id]
+    }
+
+    fn remove(&mut self, user_id: i32) -> Option<Player> {
+        let player = self.players.remove(&user_id)?;
+
+        self.entity_indices.remove(&player.entity_index);
+        if self.xuids.get(&player.xuid) == Some(&user_id) {
+            self.xuids.remove(&player.xuid);
+        }
+        Some(player)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // player_info_t as the userinfo table stores it, see PlayerInfo::parse.
+    pub fn user_info(user_id: i32, xuid: u64, name: &str) -> Vec<u8> {
+        let mut data = vec![0u8; 337];
+        data[8..16].copy_from_slice(&xuid.to_be_bytes());
+        data[16..16 + name.len()].copy_from_slice(name.as_bytes());
+        data[144..148].copy_from_slice(&user_id.to_be_bytes());
+        data
+    }
+
+    fn update(roster: &mut PlayerRoster, table_index: usize, user_data: Option<&[u8]>) -> Vec<(RosterChangeKind, i32, String, Option<String>)> {
+        let mut changes = Vec::new();
+        roster.update(table_index, user_data, |change| {
+            changes.push((change.kind, change.player.user_id, change.player.name.clone(), change.previous_name.map(str::to_owned)));
+            Ok(())
+        }).unwrap();
+        changes
+    }
+
+    #[test]
+    fn reports_joins_renames_and_leaves() {
+        let mut roster = PlayerRoster::new();
+
+        let changes = update(&mut roster, 2, Some(&user_info(5, 76561197960265728, "first")));
+        assert_eq!(changes, vec![(RosterChangeKind::Joined, 5, "first".to_owned(), None)]);
+        assert_eq!(roster.by_entity_index(3).map(|player| player.user_id), Some(5));
+        assert_eq!(roster.by_xuid(76561197960265728).map(|player| player.entity_index), Some(3));
+
+        let changes = update(&mut roster, 2, Some(&user_info(5, 76561197960265728, "second")));
+        assert_eq!(changes, vec![(RosterChangeKind::Renamed, 5, "second".to_owned(), Some("first".to_owned()))]);
+        assert_eq!(roster.by_user_id(5).map(|player| player.name.as_str()), Some("second"));
+
+        // Updates that change nothing are not reported.
+        assert!(update(&mut roster, 2, Some(&user_info(5, 76561197960265728, "second"))).is_empty());
+
+        let changes = update(&mut roster, 2, None);
+        assert_eq!(changes, vec![(RosterChangeKind::Left, 5, "second".to_owned(), None)]);
+        assert!(roster.is_empty());
+        assert!(roster.by_entity_index(3).is_none());
+        assert!(roster.by_xuid(76561197960265728).is_none());
+    }
+
+    #[test]
+    fn replaces_the_player_of_a_reused_slot() {
+        let mut roster = PlayerRoster::new();
+        update(&mut roster, 0, Some(&user_info(2, 0, "bot")));
+
+        let changes = update(&mut roster, 0, Some(&user_info(3, 0, "player")));
+        assert_eq!(changes, vec![
+            (RosterChangeKind::Left, 2, "bot".to_owned(), None),
+            (RosterChangeKind::Joined, 3, "player".to_owned(), None)
+        ]);
+        assert_eq!(roster.by_entity_index(1).map(|player| player.user_id), Some(3));
+        assert!(roster.by_user_id(2).is_none());
+
+        // Bots have no xuid and are not found by it.
+        assert!(roster.by_xuid(0).is_none());
+    }
+}
@@ -0,0 +1,438 @@
+use serde::Serialize;
+
+use crate::bitreader::BitReader;
+use crate::sendtables::*;
+use crate::{DemoError, Error};
+
+const COORD_INTEGER_BITS: u32 = 14;
+const COORD_FRACTIONAL_BITS: u32 = 5;
+const COORD_RESOLUTION: f32 = 1.0 / (1 << COORD_FRACTIONAL_BITS) as f32;
+
+const COORD_INTEGER_BITS_MP: u32 = 11;
+const COORD_FRACTIONAL_BITS_MP_LOWPRECISION: u32 = 3;
+const COORD_RESOLUTION_LOWPRECISION: f32 = 1.0 / (1 << COORD_FRACTIONAL_BITS_MP_LOWPRECISION) as f32;
+
+const NORMAL_FRACTIONAL_BITS: u32 = 11;
+const NORMAL_RESOLUTION: f32 = 1.0 / ((1 << NORMAL_FRACTIONAL_BITS) - 1) as f32;
+
+// DT_MAX_STRING_BITS
+const STRING_LENGTH_BITS: u32 = 9;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum PropValue {
+    Int(i32),
+    Float(f32),
+    Vector([f32; 3]),
+    VectorXY([f32; 2]),
+    String(String),
+    Int64(i64),
+    Array(Vec<PropValue>)
+}
+
+impl PropValue {
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            PropValue::Int(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            PropValue::Float(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    // VectorXY values are widened with a zero z component.
+    pub fn as_vector(&self) -> Option<[f32; 3]> {
+        match self {
+            PropValue::Vector(value) => Some(*value),
+            PropValue::VectorXY([x, y]) => Some([*x, *y, 0.0]),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropValue::String(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_int64(&self) -> Option<i64> {
+        match self {
+            PropValue::Int64(value) => Some(*value),
+            PropValue::Int(value) => Some(*value as i64),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[PropValue]> {
+        match self {
+            PropValue::Array(values) => Some(values),
+            _ => None
+        }
+    }
+}
+
+pub fn decode_prop(reader: &mut BitReader, prop: &FlattenedProp) -> Result<PropValue, Error> {
+    decode(reader, &prop.prop, prop.array_element.as_ref())
+}
+
+pub fn decode(reader: &mut BitReader, prop: &SendProp, array_element: Option<&SendProp>) -> Result<PropValue, Error> {
+    match prop.prop_type {
+        PropType::Int => Ok(PropValue::Int(decode_int(reader, prop)?)),
+        PropType::Float => Ok(PropValue::Float(decode_float(reader, prop)?)),
+        PropType::Vector => Ok(PropValue::Vector(decode_vector(reader, prop)?)),
+        PropType::VectorXY => Ok(PropValue::VectorXY([decode_float(reader, prop)?, decode_float(reader, prop)?])),
+        PropType::String => Ok(PropValue::String(decode_string(reader)?)),
+        PropType::Int64 => Ok(PropValue::Int64(decode_int64(reader, prop)?)),
+        PropType::Array => match array_element {
+            Some(element) => Ok(PropValue::Array(decode_array(reader, prop, element)?)),
+            None => Err(DemoError::invalid_data(format!("Array {} has no element prop", prop.var_name)))
+        },
+        prop_type => Err(DemoError::invalid_data(format!("Cannot decode {:?} prop {}", prop_type, prop.var_name)))
+    }
+}
+
+fn num_bits(prop: &SendProp) -> Result<u32, Error> {
+    match prop.num_bits {
+        bits @ 0..=32 => Ok(bits as u32),
+        bits => Err(DemoError::invalid_data(format!("Prop {} has {} bits", prop.var_name, bits)))
+    }
+}
+
+fn decode_int(reader: &mut BitReader, prop: &SendProp) -> Result<i32, Error> {
+    if prop.has_flag(SPROP_VARINT) {
+        if prop.has_flag(SPROP_UNSIGNED) {
+            Ok(reader.read_var_u32()? as i32)
+        } else {
+            reader.read_signed_var_i32()
+        }
+    } else if prop.has_flag(SPROP_UNSIGNED) {
+        Ok(reader.read_bits(num_bits(prop)?)? as i32)
+    } else {
+        reader.read_signed_bits(num_bits(prop)?)
+    }
+}
+
+fn decode_int64(reader: &mut BitReader, prop: &SendProp) -> Result<i64, Error> {
+    if prop.has_flag(SPROP_VARINT) {
+        if prop.has_flag(SPROP_UNSIGNED) {
+            return Ok(reader.read_var_u64()? as i64);
+        }
+        return reader.read_signed_var_i64();
+    }
+
+    let (negative, high_bits) = match prop.num_bits {
+        bits @ 32..=64 if prop.has_flag(SPROP_UNSIGNED) => (false, bits as u32 - 32),
+        bits @ 33..=64 => (reader.read_bit()?, bits as u32 - 32 - 1),
+        bits => return Err(DemoError::invalid_data(format!("Prop {} has {} bits", prop.var_name, bits)))
+    };
+
+    let low = reader.read_bits(32)? as u64;
+    let high = reader.read_bits(high_bits)? as u64;
+    let value = ((high << 32) | low) as i64;
+
+    Ok(if negative { value.wrapping_neg() } else { value })
+}
+
+fn decode_float(reader: &mut BitReader, prop: &SendProp) -> Result<f32, Error> {
+    if prop.has_flag(SPROP_COORD) {
+        read_bit_coord(reader)
+    } else if prop.has_flag(SPROP_COORD_MP) {
+        read_bit_coord_mp(reader, false, false)
+    } else if prop.has_flag(SPROP_COORD_MP_LOWPRECISION) {
+        read_bit_coord_mp(reader, false, true)
+    } else if prop.has_flag(SPROP_COORD_MP_INTEGRAL) {
+        read_bit_coord_mp(reader, true, false)
+    } else if prop.has_flag(SPROP_NOSCALE) {
+        reader.read_f32()
+    } else if prop.has_flag(SPROP_NORMAL) {
+        read_bit_normal(reader)
+    } else if prop.has_flag(SPROP_CELL_COORD) {
+        read_bit_cell_coord(reader, num_bits(prop)?, false, false)
+    } else if prop.has_flag(SPROP_CELL_COORD_LOWPRECISION) {
+        read_bit_cell_coord(reader, num_bits(prop)?, false, true)
+    } else if prop.has_flag(SPROP_CELL_COORD_INTEGRAL) {
+        read_bit_cell_coord(reader, num_bits(prop)?, true, false)
+    } else {
+        let bits = num_bits(prop)?;
+        let interpolated = reader.read_bits(bits)? as f32 / ((1u64 << bits) - 1) as f32;
+        Ok(prop.low_value + (prop.high_value - prop.low_value) * interpolated)
+    }
+}
+
+fn decode_vector(reader: &mut BitReader, prop: &SendProp) -> Result<[f32; 3], Error> {
+    let x = decode_float(reader, prop)?;
+    let y = decode_float(reader, prop)?;
+
+    // Normals only send the sign of z, its length follows from x and y.
+    let z = if prop.has_flag(SPROP_NORMAL) {
+        let negative = reader.read_bit()?;
+        let length_xy = x * x + y * y;
+        let z = if length_xy < 1.0 { (1.0 - length_xy).sqrt() } else { 0.0 };
+        if negative { -z } else { z }
+    } else {
+        decode_float(reader, prop)?
+    };
+
+    Ok([x, y, z])
+}
+
+// The engine copies strings into fixed size buffers, which can cut them mid character.
+fn decode_string(reader: &mut BitReader) -> Result<String, Error> {
+    let length = reader.read_bits(STRING_LENGTH_BITS)? as usize;
+    Ok(String::from_utf8_lossy(&reader.read_bytes(length)?).into_owned())
+}
+
+fn decode_array(reader: &mut BitReader, prop: &SendProp, element: &SendProp) -> Result<Vec<PropValue>, Error> {
+    // Enough bits to hold num_elements itself.
+    let max_elements = prop.num_elements.max(0) as u32;
+    let count_bits = (32 - max_elements.leading_zeros()).max(1);
+
+    let count = reader.read_bits(count_bits)?;
+    if count > max_elements {
+        return Err(DemoError::invalid_data(format!("Array {} has {} elements but holds at most {}", prop.var_name, count, max_elements)));
+    }
+
+    // Every element takes at least a bit, so the remaining data bounds the allocation.
+    let mut values = Vec::with_capacity((count as usize).min(reader.remaining()));
+    for _ in 0..count {
+        values.push(decode(reader, element, None)?);
+    }
+    Ok(values)
+}
+
+fn read_bit_coord(reader: &mut BitReader) -> Result<f32, Error> {
+    let has_integer = reader.read_bit()?;
+    let has_fraction = reader.read_bit()?;
+    if !has_integer && !has_fraction {
+        return Ok(0.0);
+    }
+
+    let negative = reader.read_bit()?;
+    let integer = if has_integer { reader.read_bits(COORD_INTEGER_BITS)? + 1 } else { 0 };
+    let fraction = if has_fraction { reader.read_bits(COORD_FRACTIONAL_BITS)? } else { 0 };
+
+    let value = integer as f32 + fraction as f32 * COORD_RESOLUTION;
+    Ok(if negative { -value } else { value })
+}
+
+fn read_bit_coord_mp(reader: &mut BitReader, integral: bool, low_precision: bool) -> Result<f32, Error> {
+    let in_bounds = reader.read_bit()?;
+    let integer_bits = if in_bounds { COORD_INTEGER_BITS_MP } else { COORD_INTEGER_BITS };
+
+    if integral {
+        if !reader.read_bit()? {
+            return Ok(0.0);
+        }
+
+        let negative = reader.read_bit()?;
+        let value = (reader.read_bits(integer_bits)? + 1) as f32;
+        return Ok(if negative { -value } else { value });
+    }
+
+    let has_integer = reader.read_bit()?;
+    let negative = reader.read_bit()?;
+    let integer = if has_integer { reader.read_bits(integer_bits)? + 1 } else { 0 };
+
+    let value = if low_precision {
+        integer as f32 + reader.read_bits(COORD_FRACTIONAL_BITS_MP_LOWPRECISION)? as f32 * COORD_RESOLUTION_LOWPRECISION
+    } else {
+        integer as f32 + reader.read_bits(COORD_FRACTIONAL_BITS)? as f32 * COORD_RESOLUTION
+    };
+    Ok(if negative { -value } else { value })
+}
+
+fn read_bit_cell_coord(reader: &mut BitReader, bits: u32, integral: bool, low_precision: bool) -> Result<f32, Error> {
+    let integer = reader.read_bits(bits)? as f32;
+
+    if integral {
+        Ok(integer)
+    } else if low_precision {
+        Ok(integer + reader.read_bits(COORD_FRACTIONAL_BITS_MP_LOWPRECISION)? as f32 * COORD_RESOLUTION_LOWPRECISION)
+    } else {
+        Ok(integer + reader.read_bits(COORD_FRACTIONAL_BITS)? as f32 * COORD_RESOLUTION)
+    }
+}
+
+fn read_bit_normal(reader: &mut BitReader) -> Result<f32, Error> {
+    let negative = reader.read_bit()?;
+    let value = reader.read_bits(NORMAL_FRACTIONAL_BITS)? as f32 * NORMAL_RESOLUTION;
+    Ok(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitreader::BitWriter;
+
+    fn prop(prop_type: PropType, flags: i32, num_bits: i32) -> SendProp {
+        SendProp {
+            prop_type,
+            var_name: "m_test".to_owned(),
+            flags,
+            priority: 0,
+            dt_name: String::new(),
+            num_elements: 0,
+            low_value: 0.0,
+            high_value: 1.0,
+            num_bits
+        }
+    }
+
+    fn decode_from(prop: &SendProp, writer: BitWriter) -> Result<PropValue, Error> {
+        decode(&mut BitReader::new(&writer.data), prop, None)
+    }
+
+    fn float_prop(flags: i32, num_bits: i32, writer: BitWriter) -> f32 {
+        decode_from(&prop(PropType::Float, flags, num_bits), writer).unwrap().as_float().unwrap()
+    }
+
+    #[test]
+    fn decodes_unsigned_int() {
+        let value = decode_from(&prop(PropType::Int, SPROP_UNSIGNED, 5), BitWriter::default().bits(21, 5)).unwrap();
+        assert_eq!(value, PropValue::Int(21));
+    }
+
+    #[test]
+    fn decodes_signed_int() {
+        let value = decode_from(&prop(PropType::Int, 0, 6), BitWriter::default().bits(0b111110, 6)).unwrap();
+        assert_eq!(value, PropValue::Int(-2));
+    }
+
+    #[test]
+    fn decodes_varint() {
+        let unsigned = decode_from(&prop(PropType::Int, SPROP_VARINT | SPROP_UNSIGNED, 32), BitWriter::default().bytes(&[0xAC, 0x02])).unwrap();
+        assert_eq!(unsigned, PropValue::Int(300));
+
+        let signed = decode_from(&prop(PropType::Int, SPROP_VARINT, 32), BitWriter::default().bytes(&[3])).unwrap();
+        assert_eq!(signed, PropValue::Int(-2));
+    }
+
+    #[test]
+    fn decodes_noscale_float() {
+        assert_eq!(float_prop(SPROP_NOSCALE, 32, BitWriter::default().float(1.5)), 1.5);
+    }
+
+    #[test]
+    fn decodes_quantized_float() {
+        let mut ranged = prop(PropType::Float, 0, 8);
+        ranged.low_value = -100.0;
+        ranged.high_value = 100.0;
+
+        assert_eq!(decode_from(&ranged, BitWriter::default().bits(255, 8)).unwrap(), PropValue::Float(100.0));
+        assert_eq!(decode_from(&ranged, BitWriter::default().bits(0, 8)).unwrap(), PropValue::Float(-100.0));
+    }
+
+    #[test]
+    fn decodes_coord() {
+        let writer = BitWriter::default().bit(true).bit(true).bit(true).bits(9, 14).bits(16, 5);
+        assert_eq!(float_prop(SPROP_COORD, 0, writer), -10.5);
+
+        assert_eq!(float_prop(SPROP_COORD, 0, BitWriter::default().bit(false).bit(false)), 0.0);
+    }
+
+    #[test]
+    fn decodes_coord_mp() {
+        let writer = BitWriter::default().bit(true).bit(true).bit(false).bits(41, 11).bits(8, 5);
+        assert_eq!(float_prop(SPROP_COORD_MP, 0, writer), 42.25);
+
+        // Out of bounds values use the full 14 integer bits.
+        let writer = BitWriter::default().bit(false).bit(true).bit(true).bits(1999, 14).bits(4, 3);
+        assert_eq!(float_prop(SPROP_COORD_MP_LOWPRECISION, 0, writer), -2000.5);
+
+        let writer = BitWriter::default().bit(true).bit(true).bit(true).bits(6, 11);
+        assert_eq!(float_prop(SPROP_COORD_MP_INTEGRAL, 0, writer), -7.0);
+    }
+
+    #[test]
+    fn decodes_cell_coord() {
+        assert_eq!(float_prop(SPROP_CELL_COORD, 5, BitWriter::default().bits(17, 5).bits(24, 5)), 17.75);
+        assert_eq!(float_prop(SPROP_CELL_COORD_LOWPRECISION, 5, BitWriter::default().bits(17, 5).bits(2, 3)), 17.25);
+        assert_eq!(float_prop(SPROP_CELL_COORD_INTEGRAL, 5, BitWriter::default().bits(17, 5)), 17.0);
+    }
+
+    #[test]
+    fn decodes_normal() {
+        assert_eq!(float_prop(SPROP_NORMAL, 0, BitWriter::default().bit(true).bits(2047, 11)), -1.0);
+    }
+
+    #[test]
+    fn decodes_vectors() {
+        let writer = BitWriter::default().float(1.0).float(2.0).float(3.0);
+        assert_eq!(decode_from(&prop(PropType::Vector, SPROP_NOSCALE, 32), writer).unwrap(), PropValue::Vector([1.0, 2.0, 3.0]));
+
+        // Normal vectors only carry the sign of z.
+        let writer = BitWriter::default().bit(false).bits(0, 11).bit(false).bits(0, 11).bit(true);
+        assert_eq!(decode_from(&prop(PropType::Vector, SPROP_NORMAL, 0), writer).unwrap(), PropValue::Vector([0.0, 0.0, -1.0]));
+
+        let writer = BitWriter::default().float(1.0).float(2.0);
+        assert_eq!(decode_from(&prop(PropType::VectorXY, SPROP_NOSCALE, 32), writer).unwrap(), PropValue::VectorXY([1.0, 2.0]));
+    }
+
+    #[test]
+    fn decodes_string() {
+        let value = decode_from(&prop(PropType::String, 0, 0), BitWriter::default().bits(5, 9).bytes(b"hello")).unwrap();
+        assert_eq!(value.as_str(), Some("hello"));
+
+        // "né" cut after the first byte of é.
+        let value = decode_from(&prop(PropType::String, 0, 0), BitWriter::default().bits(2, 9).bytes(&[b'n', 0xC3])).unwrap();
+        assert_eq!(value.as_str(), Some("n\u{FFFD}"));
+    }
+
+    #[test]
+    fn decodes_int64() {
+        let writer = BitWriter::default().bits(0x89AB_CDEF, 32).bits(0x0123_4567, 32);
+        assert_eq!(decode_from(&prop(PropType::Int64, SPROP_UNSIGNED, 64), writer).unwrap(), PropValue::Int64(0x0123_4567_89AB_CDEF));
+
+        let writer = BitWriter::default().bit(true).bits(5, 32).bits(0, 31);
+        assert_eq!(decode_from(&prop(PropType::Int64, 0, 64), writer).unwrap(), PropValue::Int64(-5));
+
+        let writer = BitWriter::default().bytes(&[1]);
+        assert_eq!(decode_from(&prop(PropType::Int64, SPROP_VARINT, 64), writer).unwrap(), PropValue::Int64(-1));
+
+        // The smallest widths the engine accepts leave no high bits.
+        let writer = BitWriter::default().bits(0xFFFF_FFFF, 32);
+        assert_eq!(decode_from(&prop(PropType::Int64, SPROP_UNSIGNED, 32), writer).unwrap(), PropValue::Int64(0xFFFF_FFFF));
+
+        let writer = BitWriter::default().bit(true).bits(7, 32);
+        assert_eq!(decode_from(&prop(PropType::Int64, 0, 33), writer).unwrap(), PropValue::Int64(-7));
+    }
+
+    #[test]
+    fn decodes_array() {
+        let mut array = prop(PropType::Array, 0, 0);
+        array.num_elements = 10;
+        let element = prop(PropType::Int, SPROP_UNSIGNED, 4);
+
+        let writer = BitWriter::default().bits(3, 4).bits(1, 4).bits(2, 4).bits(3, 4);
+        let value = decode(&mut BitReader::new(&writer.data), &array, Some(&element)).unwrap();
+        assert_eq!(value, PropValue::Array(vec![PropValue::Int(1), PropValue::Int(2), PropValue::Int(3)]));
+    }
+
+    #[test]
+    fn rejects_arrays_longer_than_their_prop() {
+        let mut array = prop(PropType::Array, 0, 0);
+        array.num_elements = 1 << 30;
+        let element = prop(PropType::Int, SPROP_UNSIGNED, 4);
+
+        let writer = BitWriter::default().bits((1 << 30) + 1, 31);
+        let result = decode(&mut BitReader::new(&writer.data), &array, Some(&element));
+        assert!(matches!(result, Err(DemoError::InvalidData { .. })));
+
+        // A count in range that the data cannot hold runs out of data instead of memory.
+        let writer = BitWriter::default().bits(1 << 30, 31);
+        let result = decode(&mut BitReader::new(&writer.data), &array, Some(&element));
+        assert!(matches!(result, Err(DemoError::Truncated { .. })));
+    }
+
+    #[test]
+    fn rejects_malformed_props() {
+        assert!(matches!(decode_from(&prop(PropType::Array, 0, 0), BitWriter::default().bits(0, 32)), Err(DemoError::InvalidData { .. })));
+        assert!(matches!(decode_from(&prop(PropType::Int, 0, 33), BitWriter::default().bits(0, 32)), Err(DemoError::InvalidData { .. })));
+        assert!(matches!(decode_from(&prop(PropType::Float, SPROP_NOSCALE, 32), BitWriter::default().bits(0, 8)), Err(DemoError::Truncated { .. })));
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::format::ServerClass;
+use crate::protos::netmessages::{CSVCMsg_SendTable, CSVCMsg_SendTable_sendprop_t};
+use crate::{DemoError, Error};
+
+pub const SPROP_UNSIGNED: i32 = 1 << 0;
+pub const SPROP_COORD: i32 = 1 << 1;
+pub const SPROP_NOSCALE: i32 = 1 << 2;
+pub const SPROP_ROUNDDOWN: i32 = 1 << 3;
+pub const SPROP_ROUNDUP: i32 = 1 << 4;
+pub const SPROP_NORMAL: i32 = 1 << 5;
+pub const SPROP_EXCLUDE: i32 = 1 << 6;
+pub const SPROP_XYZE: i32 = 1 << 7;
+pub const SPROP_INSIDEARRAY: i32 = 1 << 8;
+pub const SPROP_PROXY_ALWAYS_YES: i32 = 1 << 9;
+pub const SPROP_IS_A_VECTOR_ELEM: i32 = 1 << 10;
+pub const SPROP_COLLAPSIBLE: i32 = 1 << 11;
+pub const SPROP_COORD_MP: i32 = 1 << 12;
+pub const SPROP_COORD_MP_LOWPRECISION: i32 = 1 << 13;
+pub const SPROP_COORD_MP_INTEGRAL: i32 = 1 << 14;
+pub const SPROP_CELL_COORD: i32 = 1 << 15;
+pub const SPROP_CELL_COORD_LOWPRECISION: i32 = 1 << 16;
+pub const SPROP_CELL_COORD_INTEGRAL: i32 = 1 << 17;
+pub const SPROP_CHANGES_OFTEN: i32 = 1 << 18;
+pub const SPROP_VARINT: i32 = 1 << 19;
+
+// Props flagged SPROP_CHANGES_OFTEN are sorted as if they had this priority.
+const CHANGES_OFTEN_PRIORITY: i32 = 64;
+
+// Guards against send tables that (directly or indirectly) contain themselves.
+const MAX_TABLE_DEPTH: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PropType {
+    Int,
+    Float,
+    Vector,
+    VectorXY,
+    String,
+    Array,
+    DataTable,
+    Int64,
+    Unknown(i32)
+}
+
+impl From<i32> for PropType {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => PropType::Int,
+            1 => PropType::Float,
+            2 => PropType::Vector,
+            3 => PropType::VectorXY,
+            4 => PropType::String,
+            5 => PropType::Array,
+            6 => PropType::DataTable,
+            7 => PropType::Int64,
+            value => PropType::Unknown(value)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SendProp {
+    pub prop_type: PropType,
+    pub var_name: String,
+    pub flags: i32,
+    pub priority: i32,
+    pub dt_name: String,
+    pub num_elements: i32,
+    pub low_value: f32,
+    pub high_value: f32,
+    pub num_bits: i32
+}
+
+impl SendProp {
+    pub fn has_flag(&self, flag: i32) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+impl From<&CSVCMsg_SendTable_sendprop_t> for SendProp {
+    fn from(prop: &CSVCMsg_SendTable_sendprop_t) -> Self {
+        SendProp {
+            prop_type: prop.get_field_type().into(),
+            var_name: prop.get_var_name().to_owned(),
+            flags: prop.get_flags(),
+            priority: prop.get_priority(),
+            dt_name: prop.get_dt_name().to_owned(),
+            num_elements: prop.get_num_elements(),
+            low_value: prop.get_low_value(),
+            high_value: prop.get_high_value(),
+            num_bits: prop.get_num_bits()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SendTable {
+    pub name: String,
+    pub needs_decoder: bool,
+    pub props: Vec<SendProp>
+}
+
+impl From<&CSVCMsg_SendTable> for SendTable {
+    fn from(table: &CSVCMsg_SendTable) -> Self {
+        SendTable {
+            name: table.get_net_table_name().to_owned(),
+            needs_decoder: table.get_needs_decoder(),
+            props: table.get_props().iter().map(SendProp::from).collect()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FlattenedProp {
+    // Dotted path through non-collapsible data tables, e.g. `m_hMyWeapons.000`.
+    pub name: String,
+    pub prop: SendProp,
+    // Arrays describe their elements with the prop preceding them in the table.
+    pub array_element: Option<SendProp>
+}
+
+#[derive(Clone, Debug)]
+pub struct FlattenedClass {
+    pub id: u16,
+    pub name: String,
+    pub datatable: String,
+    // Data tables reached through `baseclass` props, nearest first.
+    pub base_tables: Vec<String>,
+    pub props: Vec<FlattenedProp>,
+    prop_indices: HashMap<String, usize>
+}
+
+impl FlattenedClass {
+    pub fn prop_index(&self, name: &str) -> Option<usize> {
+        self.prop_indices.get(name).copied()
+    }
+
+    pub fn prop(&self, name: &str) -> Option<&FlattenedProp> {
+        self.prop_index(name).map(|index| &self.props[index])
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ServerClassRegistry {
+    send_tables: HashMap<String, SendTable>,
+    server_classes: Vec<ServerClass>,
+    classes: Vec<Arc<FlattenedClass>>,
+    class_ids: HashMap<String, u16>
+}
+
+impl ServerClassRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_send_table(&mut self, table: &CSVCMsg_SendTable) {
+        let table = SendTable::from(table);
+        self.send_tables.insert(table.name.clone(), table);
+    }
+
+    pub fn add_server_class(&mut self, server_class: ServerClass) {
+        self.server_classes.push(server_class);
+    }
+
+    // Resolves every registered server class against its send table. Must be called
+    // once all send tables and server classes of a dem_datatables frame were added.
+    pub fn flatten(&mut self) -> Result<(), Error> {
+        let mut classes = Vec::with_capacity(self.server_classes.len());
+
+        for server_class in &self.server_classes {
+            let table = self.send_table(&server_class.datatable)?;
+
+            let mut excludes = HashSet::new();
+            self.gather_excludes(table, &mut excludes, 0)?;
+
+            let mut props = Vec::new();
+            self.gather_props(table, &excludes, "", &mut props, 0)?;
+            sort_by_priority(&mut props);
+
+            let prop_indices = props.iter().enumerate().map(|(index, prop)| (prop.name.clone(), index)).collect();
+
+            classes.push(Arc::new(FlattenedClass {
+                id: server_class.id,
+                name: server_class.name.clone(),
+                datatable: server_class.datatable.clone(),
+                base_tables: self.base_tables(table)?,
+                props,
+                prop_indices
+            }));
+        }
+
+        classes.sort_by_key(|class| class.id);
+        self.class_ids = classes.iter().map(|class| (class.name.clone(), class.id)).collect();
+        self.classes = classes;
+        Ok(())
+    }
+
+    pub fn class(&self, id: u16) -> Option<&Arc<FlattenedClass>> {
+        match self.classes.get(id as usize) {
+            Some(class) if class.id == id => Some(class),
+            _ => self.classes.iter().find(|class| class.id == id)
+        }
+    }
+
+    pub fn class_by_name(&self, name: &str) -> Option<&Arc<FlattenedClass>> {
+        self.class_ids.get(name).and_then(|&id| self.class(id))
+    }
+
+    pub fn props(&self, id: u16) -> Option<&[FlattenedProp]> {
+        self.class(id).map(|class| &class.props[..])
+    }
+
+    pub fn classes(&self) -> &[Arc<FlattenedClass>] {
+        &self.classes
+    }
+
+    pub fn class_count(&self) -> usize {
+        self.server_classes.len()
+    }
+
+    // Number of bits used to encode a class id in entity updates.
+    pub fn class_id_bits(&self) -> u32 {
+        let mut bits = 0;
+        while (1usize << bits) <= self.class_count() {
+            bits += 1;
+        }
+        bits
+    }
+
+    pub fn table(&self, name: &str) -> Option<&SendTable> {
+        self.send_tables.get(name)
+    }
+
+    fn send_table(&self, name: &str) -> Result<&SendTable, Error> {
+        self.send_tables.get(name).ok_or_else(|| DemoError::invalid_data(format!("Unknown send table {}", name)))
+    }
+
+    fn gather_excludes(&self, table: &SendTable, excludes: &mut HashSet<(String, String)>, depth: usize) -> Result<(), Error> {
+        if depth > MAX_TABLE_DEPTH {
+            return Err(DemoError::invalid_data(format!("Send table {} nests too deeply", table.name)));
+        }
+
+        for prop in &table.props {
+            if prop.has_flag(SPROP_EXCLUDE) {
+                excludes.insert((prop.dt_name.clone(), prop.var_name.clone()));
+            } else if prop.prop_type == PropType::DataTable {
+                self.gather_excludes(self.send_table(&prop.dt_name)?, excludes, depth + 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn gather_props(&self, table: &SendTable, excludes: &HashSet<(String, String)>, prefix: &str, props: &mut Vec<FlattenedProp>, depth: usize) -> Result<(), Error> {
+        let mut table_props = Vec::new();
+        self.iterate_props(table, excludes, prefix, &mut table_props, props, depth)?;
+        props.extend(table_props);
+        Ok(())
+    }
+
+    // Collapsible tables are merged into their parent's props while all other
+    // data tables are flattened ahead of them, just like SendTable_BuildHierarchy.
+    fn iterate_props(&self, table: &SendTable, excludes: &HashSet<(String, String)>, prefix: &str, table_props: &mut Vec<FlattenedProp>, props: &mut Vec<FlattenedProp>, depth: usize) -> Result<(), Error> {
+        if depth > MAX_TABLE_DEPTH {
+            return Err(DemoError::invalid_data(format!("Send table {} nests too deeply", table.name)));
+        }
+
+        for (index, prop) in table.props.iter().enumerate() {
+            if prop.has_flag(SPROP_INSIDEARRAY) || prop.has_flag(SPROP_EXCLUDE) {
+                continue;
+            }
+            if excludes.contains(&(table.name.clone(), prop.var_name.clone())) {
+                continue;
+            }
+
+            if prop.prop_type == PropType::DataTable {
+                let sub_table = self.send_table(&prop.dt_name)?;
+
+                if prop.has_flag(SPROP_COLLAPSIBLE) {
+                    self.iterate_props(sub_table, excludes, prefix, table_props, props, depth + 1)?;
+                } else {
+                    let prefix = format!("{}{}.", prefix, prop.var_name);
+                    self.gather_props(sub_table, excludes, &prefix, props, depth + 1)?;
+                }
+            } else {
+                let array_element = match prop.prop_type {
+                    PropType::Array if index > 0 => Some(table.props[index - 1].clone()),
+                    PropType::Array => return Err(DemoError::invalid_data(format!("Array {} has no element prop", prop.var_name))),
+                    _ => None
+                };
+
+                table_props.push(FlattenedProp {
+                    name: format!("{}{}", prefix, prop.var_name),
+                    prop: prop.clone(),
+                    array_element
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn base_tables(&self, table: &SendTable) -> Result<Vec<String>, Error> {
+        let mut base_tables = Vec::new();
+        let mut table = table;
+
+        while let Some(base) = table.props.iter().find(|prop| prop.prop_type == PropType::DataTable && prop.var_name == "baseclass") {
+            if base_tables.len() > MAX_TABLE_DEPTH {
+                return Err(DemoError::invalid_data(format!("Send table {} nests too deeply", table.name)));
+            }
+
+            base_tables.push(base.dt_name.clone());
+            table = self.send_table(&base.dt_name)?;
+        }
+
+        Ok(base_tables)
+    }
+}
+
+// Mirrors the engine's swap-based priority sort in SortProps, which is not stable.
+fn sort_by_priority(props: &mut [FlattenedProp]) {
+    let mut priorities: Vec<i32> = props.iter().map(|prop| prop.prop.priority).collect();
+    priorities.push(CHANGES_OFTEN_PRIORITY);
+    priorities.sort_unstable();
+    priorities.dedup();
+
+    let mut start = 0;
+    for priority in priorities {
+        let mut current = start;
+        while current < props.len() {
+            let prop = &props[current].prop;
+
+            if prop.priority == priority || (priority == CHANGES_OFTEN_PRIORITY && prop.has_flag(SPROP_CHANGES_OFTEN)) {
+                props.swap(start, current);
+                start += 1;
+            }
+            current += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protobuf::{RepeatedField, SingularField};
+
+    use super::*;
+
+    const INT: i32 = 0;
+    const ARRAY: i32 = 5;
+    const DATATABLE: i32 = 6;
+
+    fn prop(field_type: i32, var_name: &str, flags: i32, priority: i32) -> CSVCMsg_SendTable_sendprop_t {
+        CSVCMsg_SendTable_sendprop_t {
+            field_type: Some(field_type),
+            var_name: SingularField::some(var_name.to_owned()),
+            flags: Some(flags),
+            priority: Some(priority),
+            num_bits: Some(8),
+            ..CSVCMsg_SendTable_sendprop_t::default()
+        }
+    }
+
+    fn table_prop(var_name: &str, dt_name: &str, flags: i32) -> CSVCMsg_SendTable_sendprop_t {
+        CSVCMsg_SendTable_sendprop_t {
+            dt_name: SingularField::some(dt_name.to_owned()),
+            ..prop(DATATABLE, var_name, flags, 0)
+        }
+    }
+
+    // Flattens CTest, whose data table is the first of `tables`.
+    fn flatten(tables: Vec<(&str, Vec<CSVCMsg_SendTable_sendprop_t>)>) -> Result<ServerClassRegistry, Error> {
+        let mut registry = ServerClassRegistry::new();
+        registry.add_server_class(ServerClass { id: 0, name: "CTest".to_owned(), datatable: tables[0].0.to_owned() });
+
+        for (name, props) in tables {
+            registry.add_send_table(&CSVCMsg_SendTable {
+                net_table_name: SingularField::some(name.to_owned()),
+                props: RepeatedField::from_vec(props),
+                ..CSVCMsg_SendTable::default()
+            });
+        }
+
+        registry.flatten()?;
+        Ok(registry)
+    }
+
+    fn names(registry: &ServerClassRegistry) -> Vec<&str> {
+        registry.props(0).unwrap().iter().map(|prop| prop.name.as_str()).collect()
+    }
+
+    #[test]
+    fn leaves_out_props_excluded_anywhere_in_the_hierarchy() {
+        let registry = flatten(vec![
+            ("DT_Player", vec![
+                table_prop("baseclass", "DT_Base", 0),
+                CSVCMsg_SendTable_sendprop_t { dt_name: SingularField::some("DT_Base".to_owned()), ..prop(INT, "m_iArmor", SPROP_EXCLUDE, 0) },
+                prop(INT, "m_iAccount", 0, 0)
+            ]),
+            ("DT_Base", vec![
+                table_prop("baseclass", "DT_Entity", 0),
+                CSVCMsg_SendTable_sendprop_t { dt_name: SingularField::some("DT_Entity".to_owned()), ..prop(INT, "m_fEffects", SPROP_EXCLUDE, 0) },
+                prop(INT, "m_iHealth", 0, 0),
+                prop(INT, "m_iArmor", 0, 0)
+            ]),
+            ("DT_Entity", vec![prop(INT, "m_fEffects", 0, 0), prop(INT, "m_nModelIndex", 0, 0)])
+        ]).unwrap();
+
+        assert_eq!(names(&registry), vec!["baseclass.baseclass.m_nModelIndex", "baseclass.m_iHealth", "m_iAccount"]);
+        assert_eq!(registry.class(0).unwrap().base_tables, vec!["DT_Base", "DT_Entity"]);
+    }
+
+    #[test]
+    fn merges_collapsible_tables_and_flattens_the_others_first() {
+        let registry = flatten(vec![
+            ("DT_Player", vec![
+                prop(INT, "m_iHealth", 0, 0),
+                table_prop("m_Local", "DT_Local", SPROP_COLLAPSIBLE),
+                prop(INT, "m_iAccount", 0, 0),
+                table_prop("m_hMyWeapons", "DT_Weapons", 0)
+            ]),
+            ("DT_Local", vec![prop(INT, "m_iFOV", 0, 0)]),
+            ("DT_Weapons", vec![prop(INT, "000", 0, 0), prop(INT, "001", 0, 0)])
+        ]).unwrap();
+
+        assert_eq!(names(&registry), vec!["m_hMyWeapons.000", "m_hMyWeapons.001", "m_iHealth", "m_iFOV", "m_iAccount"]);
+    }
+
+    #[test]
+    fn arrays_take_the_preceding_prop_as_their_element() {
+        let registry = flatten(vec![
+            ("DT_Player", vec![
+                CSVCMsg_SendTable_sendprop_t { num_bits: Some(10), ..prop(INT, "m_iAmmo", SPROP_INSIDEARRAY, 0) },
+                CSVCMsg_SendTable_sendprop_t { num_elements: Some(32), ..prop(ARRAY, "m_iAmmo", 0, 0) }
+            ])
+        ]).unwrap();
+
+        let props = registry.props(0).unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props[0].prop.prop_type, PropType::Array);
+        let element = props[0].array_element.as_ref().unwrap();
+        assert_eq!((element.var_name.as_str(), element.num_bits), ("m_iAmmo", 10));
+
+        assert!(flatten(vec![("DT_Player", vec![prop(ARRAY, "m_iAmmo", 0, 0)])]).is_err());
+    }
+
+    #[test]
+    fn sorts_props_that_change_often_as_priority_64() {
+        let registry = flatten(vec![
+            ("DT_Player", vec![
+                prop(INT, "a", 0, 128),
+                prop(INT, "b", SPROP_CHANGES_OFTEN, 128),
+                prop(INT, "c", 0, 64),
+                prop(INT, "d", 0, 0),
+                prop(INT, "e", 0, 1)
+            ])
+        ]).unwrap();
+
+        // b moves in with the priority 64 props, and the swaps leave a behind it.
+        assert_eq!(names(&registry), vec!["d", "e", "c", "b", "a"]);
+        assert_eq!(registry.class(0).unwrap().prop_index("b"), Some(3));
+    }
+}
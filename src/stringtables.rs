@@ -0,0 +1,395 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+use crate::bitreader::BitReader;
+use crate::compression::decompress;
+use crate::format::{StringTableEntry, StringTablesSnapshot};
+use crate::protos::netmessages::{CSVCMsg_CreateStringTable, CSVCMsg_UpdateStringTable};
+use crate::{DemoError, Error, ParseOptions, DEFAULT_MAX_FRAME_SIZE};
+
+// Keys can be built from a prefix of one of the last 32 keys.
+const HISTORY_SIZE: usize = 32;
+const SUBSTRING_BITS: u32 = 5;
+const MAX_USERDATA_BITS: u32 = 14;
+
+// The engine requires max_entries to be a power of two no larger than this.
+const MAX_ENTRIES: i32 = 1 << 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct StringTableEntryUpdate<'a> {
+    pub table: &'a str,
+    pub index: usize,
+    pub key: &'a str,
+    pub user_data: Option<&'a [u8]>
+}
+
+#[derive(Clone, Debug)]
+pub struct StringTable {
+    pub name: String,
+    pub max_entries: i32,
+    pub user_data_fixed_size: bool,
+    pub user_data_size: i32,
+    pub user_data_size_bits: i32,
+    pub flags: i32,
+    entries: Vec<StringTableEntry>,
+    skipped_updates: u32
+}
+
+impl StringTable {
+    pub fn entries(&self) -> &[StringTableEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&StringTableEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn find(&self, key: &str) -> Option<(usize, &StringTableEntry)> {
+        self.entries.iter().enumerate().find(|(_, entry)| entry.name == key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Dictionary encoded updates, which leave the table unchanged in lenient mode.
+    pub fn skipped_updates(&self) -> u32 {
+        self.skipped_updates
+    }
+
+    pub fn entry_update(&self, index: usize) -> Option<StringTableEntryUpdate<'_>> {
+        self.entries.get(index).map(|entry| StringTableEntryUpdate {
+            table: &self.name,
+            index,
+            key: &entry.name,
+            user_data: entry.user_data.as_deref()
+        })
+    }
+
+    // Mirrors CNetworkStringTable::ParseUpdate.
+    fn parse_update<F>(&mut self, data: &[u8], entry_count: i32, lenient: bool, on_entry: &mut F) -> Result<(), Error>
+    where
+        F: FnMut(&StringTable, usize) -> Result<(), Error>
+    {
+        let reader = &mut BitReader::new(data);
+
+        // Entries encoded against the engine's string dictionaries cannot be decoded without
+        // them. Like demoinfogo, lenient parsing skips the update and keeps the table as is.
+        if reader.read_bit()? {
+            if lenient {
+                self.skipped_updates += 1;
+                return Ok(());
+            }
+            return Err(DemoError::invalid_data(format!("String table {} is dictionary encoded", self.name)));
+        }
+
+        let index_bits = 31u32.saturating_sub(self.max_entries.max(1).leading_zeros());
+        let mut history: VecDeque<String> = VecDeque::with_capacity(HISTORY_SIZE);
+        let mut last_index = -1i64;
+
+        for _ in 0..entry_count {
+            let index = if reader.read_bit()? { last_index + 1 } else { reader.read_bits(index_bits)? as i64 };
+            if index < 0 || index >= self.max_entries as i64 {
+                return Err(DemoError::invalid_data(format!("String table {} index {} out of range", self.name, index)));
+            }
+            last_index = index;
+            let index = index as usize;
+
+            let name = if reader.read_bit()? {
+                if reader.read_bit()? {
+                    let history_index = reader.read_bits(5)? as usize;
+                    let length = reader.read_bits(SUBSTRING_BITS)? as usize;
+
+                    let prefix = history.get(history_index).ok_or_else(|| {
+                        DemoError::invalid_data(format!("String table {} references missing history entry {}", self.name, history_index))
+                    })?;
+                    let mut name = prefix.as_bytes()[..length.min(prefix.len())].to_vec();
+                    name.extend(reader.read_string()?.into_bytes());
+                    Some(String::from_utf8(name)?)
+                } else {
+                    Some(reader.read_string()?)
+                }
+            } else {
+                None
+            };
+
+            let user_data = if reader.read_bit()? {
+                if self.user_data_fixed_size {
+                    Some(reader.read_bits_to_bytes(self.user_data_size_bits.max(0) as usize)?)
+                } else {
+                    let size = reader.read_bits(MAX_USERDATA_BITS)? as usize;
+                    Some(reader.read_bytes(size)?)
+                }
+            } else {
+                None
+            };
+
+            if index >= self.entries.len() {
+                self.entries.resize_with(index + 1, || StringTableEntry { name: String::new(), user_data: None });
+            }
+
+            let entry = &mut self.entries[index];
+            if let Some(name) = name {
+                entry.name = name;
+            }
+            // Like SetStringUserData, an entry without user data clears it. The server does
+            // this to a player's userinfo entry when they disconnect.
+            entry.user_data = user_data;
+
+            if history.len() == HISTORY_SIZE {
+                history.pop_front();
+            }
+            history.push_back(entry.name.clone());
+
+            on_entry(self, index)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Tables are referenced by UpdateStringTable in the order they were created.
+#[derive(Clone, Debug)]
+pub struct StringTables {
+    tables: Vec<StringTable>,
+    // Upper bound for the size of decompressed string data.
+    max_data_size: u32,
+    lenient: bool
+}
+
+impl Default for StringTables {
+    fn default() -> Self {
+        StringTables {
+            tables: Vec::new(),
+            max_data_size: DEFAULT_MAX_FRAME_SIZE,
+            lenient: false
+        }
+    }
+}
+
+impl StringTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: &ParseOptions) -> Self {
+        StringTables {
+            max_data_size: options.max_frame_size,
+            lenient: options.lenient,
+            ..Self::default()
+        }
+    }
+
+    pub fn tables(&self) -> &[StringTable] {
+        &self.tables
+    }
+
+    pub fn table(&self, id: usize) -> Option<&StringTable> {
+        self.tables.get(id)
+    }
+
+    pub fn table_by_name(&self, name: &str) -> Option<&StringTable> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    pub fn create<F>(&mut self, message: &CSVCMsg_CreateStringTable, mut on_entry: F) -> Result<(), Error>
+    where
+        F: FnMut(&StringTable, usize) -> Result<(), Error>
+    {
+        if message.get_max_entries() > MAX_ENTRIES {
+            return Err(DemoError::limit_exceeded(message.get_max_entries() as u64, MAX_ENTRIES as u64));
+        }
+
+        let mut table = StringTable {
+            name: message.get_name().to_owned(),
+            max_entries: message.get_max_entries(),
+            user_data_fixed_size: message.get_user_data_fixed_size(),
+            user_data_size: message.get_user_data_size(),
+            user_data_size_bits: message.get_user_data_size_bits(),
+            flags: message.get_flags(),
+            entries: Vec::new(),
+            skipped_updates: 0
+        };
+
+        let data = self.string_data(message.get_string_data());
+        table.parse_update(&data, message.get_num_entries(), self.lenient, &mut on_entry)?;
+        self.tables.push(table);
+        Ok(())
+    }
+
+    pub fn update<F>(&mut self, message: &CSVCMsg_UpdateStringTable, mut on_entry: F) -> Result<(), Error>
+    where
+        F: FnMut(&StringTable, usize) -> Result<(), Error>
+    {
+        let table_id = message.get_table_id();
+        let (data, lenient) = (self.string_data(message.get_string_data()), self.lenient);
+
+        let table = usize::try_from(table_id).ok().and_then(|id| self.tables.get_mut(id)).ok_or_else(|| {
+            DemoError::invalid_data(format!("Update for unknown string table {}", table_id))
+        })?;
+
+        table.parse_update(&data, message.get_num_changed_entries(), lenient, &mut on_entry)
+    }
+
+    // Compressed data starts with one of the magics COM_BufferToBufferDecompress looks for.
+    // Raw entry data can start with the same bytes, so it is used as is when it does not
+    // decompress.
+    fn string_data<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        decompress(data, self.max_data_size).unwrap_or(Cow::Borrowed(data))
+    }
+
+    // Replaces the contents of already created tables with a dem_stringtables snapshot.
+    // Tables the snapshot has but which were never created are skipped.
+    pub fn apply_snapshot<F>(&mut self, snapshot: &StringTablesSnapshot, mut on_entry: F) -> Result<(), Error>
+    where
+        F: FnMut(&StringTable, usize) -> Result<(), Error>
+    {
+        for snapshot_table in &snapshot.tables {
+            if let Some(table) = self.tables.iter_mut().find(|table| table.name == snapshot_table.name) {
+                table.entries = snapshot_table.entries.clone();
+
+                for index in 0..table.entries.len() {
+                    on_entry(table, index)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protobuf::SingularField;
+
+    use super::*;
+    use crate::bitreader::BitWriter;
+
+    fn create_message(max_entries: i32, num_entries: i32, string_data: Vec<u8>) -> CSVCMsg_CreateStringTable {
+        CSVCMsg_CreateStringTable {
+            name: SingularField::some("test".to_owned()),
+            max_entries: Some(max_entries),
+            num_entries: Some(num_entries),
+            string_data: SingularField::some(string_data),
+            ..CSVCMsg_CreateStringTable::default()
+        }
+    }
+
+    #[test]
+    fn creates_and_updates_entries() {
+        let data = BitWriter::default()
+            .bit(false)
+            // "first" with two bytes of user data.
+            .bit(true).bit(true).bit(false).bytes(b"first\0").bit(true).bits(2, 14).bytes(&[1, 2])
+            // The first five characters of history entry 0 followed by "ly".
+            .bit(true).bit(true).bit(true).bits(0, 5).bits(5, 5).bytes(b"ly\0").bit(false);
+
+        let mut tables = StringTables::new();
+        let mut updated = Vec::new();
+        tables.create(&create_message(4, 2, data.data), |_, index| { updated.push(index); Ok(()) }).unwrap();
+
+        // Replaces the user data of entry 0 and keeps its name.
+        let data = BitWriter::default().bit(false).bit(false).bits(0, 2).bit(false).bit(true).bits(1, 14).bytes(&[9]);
+        let update = CSVCMsg_UpdateStringTable {
+            table_id: Some(0),
+            num_changed_entries: Some(1),
+            string_data: SingularField::some(data.data),
+            ..CSVCMsg_UpdateStringTable::default()
+        };
+        tables.update(&update, |_, index| { updated.push(index); Ok(()) }).unwrap();
+
+        let table = tables.table_by_name("test").unwrap();
+        assert_eq!(updated, vec![0, 1, 0]);
+        assert_eq!(table.entry(0).map(|entry| (entry.name.as_str(), entry.user_data.as_deref())), Some(("first", Some(&[9u8][..]))));
+        assert_eq!(table.entry(1).map(|entry| (entry.name.as_str(), entry.user_data.as_deref())), Some(("firstly", None)));
+    }
+
+    #[test]
+    fn clears_user_data_an_update_does_not_carry() {
+        // Entry 0 "player" with one byte of user data.
+        let data = BitWriter::default().bit(false).bit(true).bit(true).bit(false).bytes(b"player\0").bit(true).bits(1, 14).bytes(&[7]);
+        let mut tables = StringTables::new();
+        tables.create(&create_message(4, 1, data.data), |_, _| Ok(())).unwrap();
+        assert_eq!(tables.table(0).unwrap().entry(0).unwrap().user_data.as_deref(), Some(&[7u8][..]));
+
+        // Entry 0 again, without a name or user data.
+        let data = BitWriter::default().bit(false).bit(false).bits(0, 2).bit(false).bit(false);
+        let update = CSVCMsg_UpdateStringTable {
+            table_id: Some(0),
+            num_changed_entries: Some(1),
+            string_data: SingularField::some(data.data),
+            ..CSVCMsg_UpdateStringTable::default()
+        };
+        tables.update(&update, |_, _| Ok(())).unwrap();
+
+        let entry = tables.table(0).unwrap().entry(0).unwrap();
+        assert_eq!((entry.name.as_str(), entry.user_data.as_deref()), ("player", None));
+    }
+
+    // LZSS with nothing but literals, see CLZSS::Uncompress.
+    fn lzss(data: &[u8]) -> Vec<u8> {
+        let mut compressed = b"LZSS".to_vec();
+        compressed.extend(&(data.len() as u32).to_le_bytes());
+
+        let mut chunks = data.chunks(8);
+        loop {
+            // A set command bit after the last literal ends the data.
+            let chunk = chunks.next().unwrap_or(&[]);
+            compressed.push(if chunk.len() == 8 { 0 } else { 1 << chunk.len() });
+            compressed.extend(chunk);
+            if chunk.len() < 8 {
+                compressed.extend(&[0, 0]);
+                return compressed;
+            }
+        }
+    }
+
+    #[test]
+    fn decompresses_lzss_string_data() {
+        let data = BitWriter::default().bit(false).bit(true).bit(true).bit(false).bytes(b"compressed\0").bit(false);
+        let mut tables = StringTables::new();
+        tables.create(&create_message(4, 1, lzss(&data.data)), |_, _| Ok(())).unwrap();
+
+        assert_eq!(tables.table(0).unwrap().entry(0).map(|entry| entry.name.as_str()), Some("compressed"));
+    }
+
+    #[test]
+    fn skips_dictionary_encoded_updates_when_lenient() {
+        let data = BitWriter::default().bit(false).bit(true).bit(true).bit(false).bytes(b"kept\0").bit(false);
+        let update = CSVCMsg_UpdateStringTable {
+            table_id: Some(0),
+            num_changed_entries: Some(1),
+            string_data: SingularField::some(BitWriter::default().bit(true).bits(0, 8).data),
+            ..CSVCMsg_UpdateStringTable::default()
+        };
+
+        let mut strict = StringTables::new();
+        strict.create(&create_message(4, 1, data.data.clone()), |_, _| Ok(())).unwrap();
+        assert!(matches!(strict.update(&update, |_, _| Ok(())), Err(DemoError::InvalidData { .. })));
+
+        let options = ParseOptions { lenient: true, ..ParseOptions::default() };
+        let mut lenient = StringTables::with_options(&options);
+        lenient.create(&create_message(4, 1, data.data), |_, _| Ok(())).unwrap();
+        lenient.update(&update, |_, _| panic!("skipped updates change no entries")).unwrap();
+
+        let table = lenient.table(0).unwrap();
+        assert_eq!((table.len(), table.skipped_updates()), (1, 1));
+        assert_eq!(table.entry(0).map(|entry| entry.name.as_str()), Some("kept"));
+    }
+
+    #[test]
+    fn parses_data_that_starts_like_a_compressed_payload() {
+        // Reads as entry 3 without a name or user data.
+        let mut tables = StringTables::new();
+        tables.create(&create_message(4, 1, b"LZSS".to_vec()), |_, _| Ok(())).unwrap();
+
+        let table = tables.table(0).unwrap();
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.entry(3).map(|entry| entry.name.as_str()), Some(""));
+    }
+}
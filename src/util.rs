@@ -3,27 +3,25 @@ use std::io::Read;
 
 use bytes::{Buf, BytesMut};
 
-use crate::Error;
+use crate::{DemoError, Error};
 
 pub fn read_varuint<R: Read + ?Sized>(reader: &mut R) -> Result<(u32, usize), Error> {
     let mut decoded_value = 0;
     let mut raw_buffer = [0; 1];
-    let mut next_byte = 0;
 
     for shift_amount in 0..5 {
-        if reader.read(&mut raw_buffer)? == 1 {
-            next_byte = raw_buffer[0];
-        } else {
-            Err("Unexpected EOF")?;
+        if reader.read(&mut raw_buffer)? != 1 {
+            return Err(DemoError::truncated());
         }
 
-        decoded_value |= ((next_byte & 0x7F) as u32) << shift_amount * 7;
+        let next_byte = raw_buffer[0];
+        decoded_value |= ((next_byte & 0x7F) as u32) << (shift_amount * 7);
         if next_byte & 0x80 == 0 {
             return Ok((decoded_value, shift_amount + 1));
         }
     }
 
-    Err("Invalid VarInt")?
+    Err(DemoError::invalid_varint())
 }
 
 pub trait ReadExt: Read {
@@ -33,6 +31,12 @@ pub trait ReadExt: Read {
         Ok(buf.get_u16_le())
     }
 
+    fn read_i32_le(&mut self) -> Result<i32, Error> {
+        let mut buf = BytesMut::from(&[0u8; 4][..]);
+        self.read_exact(&mut buf)?;
+        Ok(buf.get_i32_le())
+    }
+
     fn read_u8(&mut self) -> Result<u8, Error> {
         let mut buf = [0u8; 1];
         self.read_exact(&mut buf)?;
@@ -41,3 +45,43 @@ pub trait ReadExt: Read {
 }
 
 impl<R: Read + ?Sized> ReadExt for R {}
+
+pub struct CountingReader<R> {
+    inner: R,
+    position: u64,
+    // Set once the inner reader ran out of input.
+    eof: bool
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        CountingReader {
+            inner,
+            position: 0,
+            eof: false
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn eof(&self) -> bool {
+        self.eof
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = match self.inner.read(buf) {
+            Ok(read) => read,
+            Err(error) => {
+                self.eof |= error.kind() == std::io::ErrorKind::UnexpectedEof;
+                return Err(error);
+            }
+        };
+        self.eof |= read == 0 && !buf.is_empty();
+        self.position += read as u64;
+        Ok(read)
+    }
+}
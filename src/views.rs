@@ -0,0 +1,460 @@
+use crate::entities::{Entity, EntityHandle, EntityTable};
+use crate::props::PropValue;
+
+const PLAYER_CLASS: &str = "CCSPlayer";
+const TEAM_CLASS: &str = "CCSTeam";
+const GAME_RULES_CLASS: &str = "CCSGameRulesProxy";
+const PLAYER_RESOURCE_CLASS: &str = "CCSPlayerResource";
+const WEAPON_TABLE: &str = "DT_BaseCombatWeapon";
+
+// MAX_WEAPONS, the length of m_hMyWeapons.
+const MAX_WEAPONS: usize = 64;
+
+fn resolve_handle<'a>(entities: &'a EntityTable, value: &PropValue) -> Option<&'a Entity> {
+    entities.get_by_handle(EntityHandle::from_prop(value)?)
+}
+
+fn int(entity: &Entity, name: &str) -> Option<i32> {
+    entity.prop(name).and_then(PropValue::as_int)
+}
+
+fn float(entity: &Entity, name: &str) -> Option<f32> {
+    entity.prop(name).and_then(PropValue::as_float)
+}
+
+fn string<'a>(entity: &'a Entity, name: &str) -> Option<&'a str> {
+    entity.prop(name).and_then(PropValue::as_str)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Player<'a> {
+    pub entity: &'a Entity,
+    entities: &'a EntityTable
+}
+
+impl<'a> Player<'a> {
+    pub fn new(entity: &'a Entity, entities: &'a EntityTable) -> Option<Self> {
+        if entity.class_name() != PLAYER_CLASS {
+            return None;
+        }
+        Some(Player { entity, entities })
+    }
+
+    // Entity index, one more than the player's slot in the userinfo table.
+    pub fn index(&self) -> u32 {
+        self.entity.index
+    }
+
+    // The owning client receives cslocaldata, everybody else csnonlocaldata.
+    pub fn position(&self) -> Option<[f32; 3]> {
+        ["cslocaldata", "csnonlocaldata"].iter().find_map(|table| {
+            let [x, y, _] = self.entity.prop(&format!("{}.m_vecOrigin", table))?.as_vector()?;
+            let z = float(self.entity, &format!("{}.m_vecOrigin[2]", table))?;
+            Some([x, y, z])
+        })
+    }
+
+    // Pitch and yaw.
+    pub fn eye_angles(&self) -> Option<[f32; 2]> {
+        Some([float(self.entity, "m_angEyeAngles[0]")?, float(self.entity, "m_angEyeAngles[1]")?])
+    }
+
+    pub fn health(&self) -> Option<i32> {
+        int(self.entity, "m_iHealth")
+    }
+
+    pub fn armor(&self) -> Option<i32> {
+        int(self.entity, "m_ArmorValue")
+    }
+
+    pub fn money(&self) -> Option<i32> {
+        int(self.entity, "m_iAccount")
+    }
+
+    pub fn team_num(&self) -> Option<i32> {
+        int(self.entity, "m_iTeamNum")
+    }
+
+    pub fn team(&self) -> Option<Team<'a>> {
+        let team_num = self.team_num()?;
+        self.entities.teams().find(|team| team.team_num() == Some(team_num))
+    }
+
+    // LIFE_ALIVE
+    pub fn is_alive(&self) -> bool {
+        int(self.entity, "m_lifeState") == Some(0)
+    }
+
+    pub fn last_place_name(&self) -> Option<&'a str> {
+        string(self.entity, "m_szLastPlaceName")
+    }
+
+    pub fn active_weapon(&self) -> Option<Weapon<'a>> {
+        let entity = resolve_handle(self.entities, self.entity.prop("m_hActiveWeapon")?)?;
+        Weapon::new(entity, self.entities)
+    }
+
+    pub fn weapons(&self) -> impl Iterator<Item = Weapon<'a>> + 'a {
+        let (player, entities) = (self.entity, self.entities);
+        (0..MAX_WEAPONS)
+            .filter_map(move |slot| player.prop(&format!("m_hMyWeapons.{:03}", slot)))
+            .filter_map(move |handle| resolve_handle(entities, handle))
+            .filter_map(move |entity| Weapon::new(entity, entities))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Weapon<'a> {
+    pub entity: &'a Entity,
+    entities: &'a EntityTable
+}
+
+impl<'a> Weapon<'a> {
+    // Accepts every class derived from CBaseCombatWeapon, including grenades and the C4.
+    pub fn new(entity: &'a Entity, entities: &'a EntityTable) -> Option<Self> {
+        let class = &entity.class;
+        if class.datatable != WEAPON_TABLE && !class.base_tables.iter().any(|table| table == WEAPON_TABLE) {
+            return None;
+        }
+        Some(Weapon { entity, entities })
+    }
+
+    pub fn item_definition_index(&self) -> Option<i32> {
+        int(self.entity, "m_AttributeManager.m_Item.m_iItemDefinitionIndex")
+    }
+
+    pub fn clip(&self) -> Option<i32> {
+        int(self.entity, "m_iClip1")
+    }
+
+    pub fn reserve(&self) -> Option<i32> {
+        int(self.entity, "m_iPrimaryReserveAmmoCount")
+    }
+
+    // Dropped weapons have no owner.
+    pub fn owner(&self) -> Option<Player<'a>> {
+        let entity = resolve_handle(self.entities, self.entity.prop("m_hOwner")?)?;
+        Player::new(entity, self.entities)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Team<'a> {
+    pub entity: &'a Entity,
+    entities: &'a EntityTable
+}
+
+impl<'a> Team<'a> {
+    pub fn new(entity: &'a Entity, entities: &'a EntityTable) -> Option<Self> {
+        if entity.class_name() != TEAM_CLASS {
+            return None;
+        }
+        Some(Team { entity, entities })
+    }
+
+    pub fn team_num(&self) -> Option<i32> {
+        int(self.entity, "m_iTeamNum")
+    }
+
+    // TERRORIST, CT, Spectator or Unassigned.
+    pub fn name(&self) -> Option<&'a str> {
+        string(self.entity, "m_szTeamname")
+    }
+
+    pub fn clan_tag(&self) -> Option<&'a str> {
+        string(self.entity, "m_szClanTeamname")
+    }
+
+    pub fn score(&self) -> Option<i32> {
+        int(self.entity, "m_scoreTotal")
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = Player<'a>> + 'a {
+        let team_num = self.team_num();
+        self.entities.players().filter(move |player| team_num.is_some() && player.team_num() == team_num)
+    }
+}
+
+// m_gamePhase, GamePhase in cs_gamerules.h.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GamePhase {
+    Warmup,
+    Standard,
+    FirstHalf,
+    SecondHalf,
+    Halftime,
+    MatchEnded,
+    Unknown(i32)
+}
+
+impl From<i32> for GamePhase {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => GamePhase::Warmup,
+            1 => GamePhase::Standard,
+            2 => GamePhase::FirstHalf,
+            3 => GamePhase::SecondHalf,
+            4 => GamePhase::Halftime,
+            5 => GamePhase::MatchEnded,
+            value => GamePhase::Unknown(value)
+        }
+    }
+}
+
+// Where the current round stands, derived from the warmup and freeze time flags and
+// m_iRoundWinStatus, which is WINNER_NONE until the round has been decided.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RoundPhase {
+    Warmup,
+    FreezeTime,
+    Live,
+    Ended
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GameRules<'a> {
+    pub entity: &'a Entity
+}
+
+impl<'a> GameRules<'a> {
+    pub fn new(entity: &'a Entity) -> Option<Self> {
+        if entity.class_name() != GAME_RULES_CLASS {
+            return None;
+        }
+        Some(GameRules { entity })
+    }
+
+    fn bool(&self, name: &str) -> Option<bool> {
+        int(self.entity, &format!("cs_gamerules_data.{}", name)).map(|value| value != 0)
+    }
+
+    // Phase of the match, e.g. the half being played. See round_phase for the current round.
+    pub fn game_phase(&self) -> Option<GamePhase> {
+        int(self.entity, "cs_gamerules_data.m_gamePhase").map(GamePhase::from)
+    }
+
+    pub fn round_phase(&self) -> Option<RoundPhase> {
+        if self.is_warmup_period()? {
+            return Some(RoundPhase::Warmup);
+        }
+        if self.is_freeze_period()? {
+            return Some(RoundPhase::FreezeTime);
+        }
+        match int(self.entity, "cs_gamerules_data.m_iRoundWinStatus")? {
+            0 => Some(RoundPhase::Live),
+            _ => Some(RoundPhase::Ended)
+        }
+    }
+
+    pub fn is_freeze_period(&self) -> Option<bool> {
+        self.bool("m_bFreezePeriod")
+    }
+
+    pub fn is_bomb_planted(&self) -> Option<bool> {
+        self.bool("m_bBombPlanted")
+    }
+
+    pub fn is_warmup_period(&self) -> Option<bool> {
+        self.bool("m_bWarmupPeriod")
+    }
+
+    pub fn total_rounds_played(&self) -> Option<i32> {
+        int(self.entity, "cs_gamerules_data.m_totalRoundsPlayed")
+    }
+}
+
+// Scoreboard values, stored in per player arrays indexed by entity index.
+#[derive(Copy, Clone, Debug)]
+pub struct PlayerResource<'a> {
+    pub entity: &'a Entity,
+    entities: &'a EntityTable
+}
+
+impl<'a> PlayerResource<'a> {
+    pub fn new(entity: &'a Entity, entities: &'a EntityTable) -> Option<Self> {
+        if entity.class_name() != PLAYER_RESOURCE_CLASS {
+            return None;
+        }
+        Some(PlayerResource { entity, entities })
+    }
+
+    fn value(&self, name: &str, player: &Player) -> Option<i32> {
+        int(self.entity, &format!("{}.{:03}", name, player.index()))
+    }
+
+    pub fn kills(&self, player: &Player) -> Option<i32> {
+        self.value("m_iKills", player)
+    }
+
+    pub fn deaths(&self, player: &Player) -> Option<i32> {
+        self.value("m_iDeaths", player)
+    }
+
+    pub fn assists(&self, player: &Player) -> Option<i32> {
+        self.value("m_iAssists", player)
+    }
+
+    pub fn mvps(&self, player: &Player) -> Option<i32> {
+        self.value("m_iMVPs", player)
+    }
+
+    pub fn ping(&self, player: &Player) -> Option<i32> {
+        self.value("m_iPing", player)
+    }
+
+    // Every player entity, the resource arrays hold a value for each of them.
+    pub fn players(&self) -> impl Iterator<Item = Player<'a>> + 'a {
+        self.entities.players()
+    }
+}
+
+// Entry points for the views, e.g. `tick_end.entities.players()`.
+impl EntityTable {
+    pub fn players(&self) -> impl Iterator<Item = Player<'_>> {
+        self.iter_by_class(PLAYER_CLASS).filter_map(move |entity| Player::new(entity, self))
+    }
+
+    pub fn weapons(&self) -> impl Iterator<Item = Weapon<'_>> {
+        self.iter().filter_map(move |entity| Weapon::new(entity, self))
+    }
+
+    pub fn teams(&self) -> impl Iterator<Item = Team<'_>> {
+        self.iter_by_class(TEAM_CLASS).filter_map(move |entity| Team::new(entity, self))
+    }
+
+    pub fn game_rules(&self) -> Option<GameRules<'_>> {
+        self.iter_by_class(GAME_RULES_CLASS).find_map(GameRules::new)
+    }
+
+    pub fn player_resource(&self) -> Option<PlayerResource<'_>> {
+        self.iter_by_class(PLAYER_RESOURCE_CLASS).find_map(|entity| PlayerResource::new(entity, self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitreader::BitWriter;
+    use crate::entities::testing::*;
+
+    const HANDLE_BITS: i32 = 21;
+
+    fn entities() -> EntityTable {
+        let registry = registry(&[
+            ("CCSPlayer", "DT_CSPlayer", &[
+                ("m_iHealth", 8), ("m_ArmorValue", 8), ("m_iAccount", 16), ("m_iTeamNum", 2), ("m_lifeState", 2),
+                ("m_hActiveWeapon", HANDLE_BITS), ("m_hMyWeapons.000", HANDLE_BITS), ("m_hMyWeapons.001", HANDLE_BITS)
+            ]),
+            ("CAK47", "DT_BaseCombatWeapon", &[
+                ("m_AttributeManager.m_Item.m_iItemDefinitionIndex", 16), ("m_iClip1", 8), ("m_iPrimaryReserveAmmoCount", 8),
+                ("m_hOwner", HANDLE_BITS)
+            ]),
+            ("CCSTeam", "DT_CSTeam", &[("m_iTeamNum", 2), ("m_scoreTotal", 8)]),
+            ("CCSGameRulesProxy", "DT_CSGameRulesProxy", &[
+                ("cs_gamerules_data.m_gamePhase", 3), ("cs_gamerules_data.m_bWarmupPeriod", 1),
+                ("cs_gamerules_data.m_bFreezePeriod", 1), ("cs_gamerules_data.m_iRoundWinStatus", 2),
+                ("cs_gamerules_data.m_bBombPlanted", 1), ("cs_gamerules_data.m_totalRoundsPlayed", 8)
+            ]),
+            ("CCSPlayerResource", "DT_CSPlayerResource", &[
+                ("m_iKills.001", 8), ("m_iDeaths.001", 8), ("m_iAssists.001", 8), ("m_iMVPs.001", 8), ("m_iPing.001", 8)
+            ])
+        ]);
+
+        let rifle = EntityHandle::new(2, 5).0;
+        let player = EntityHandle::new(1, 1).0;
+        // Entity 6 has serial 1, this handle is left over from an earlier weapon in its slot.
+        let stale = EntityHandle::new(6, 9).0;
+
+        let writer = enter(BitWriter::default(), &registry, 1, "CCSPlayer", 1, &[
+            ("m_iHealth", 100), ("m_ArmorValue", 50), ("m_iAccount", 800), ("m_iTeamNum", 3), ("m_lifeState", 0),
+            ("m_hActiveWeapon", rifle), ("m_hMyWeapons.000", rifle), ("m_hMyWeapons.001", stale)
+        ]);
+        let writer = enter(writer, &registry, 0, "CAK47", 5, &[
+            ("m_AttributeManager.m_Item.m_iItemDefinitionIndex", 7), ("m_iClip1", 30), ("m_iPrimaryReserveAmmoCount", 90),
+            ("m_hOwner", player)
+        ]);
+        let writer = enter(writer, &registry, 0, "CCSTeam", 1, &[("m_iTeamNum", 3), ("m_scoreTotal", 4)]);
+        let writer = enter(writer, &registry, 0, "CCSGameRulesProxy", 1, &[
+            ("cs_gamerules_data.m_gamePhase", 2), ("cs_gamerules_data.m_bWarmupPeriod", 0),
+            ("cs_gamerules_data.m_bFreezePeriod", 1), ("cs_gamerules_data.m_iRoundWinStatus", 0),
+            ("cs_gamerules_data.m_bBombPlanted", 0), ("cs_gamerules_data.m_totalRoundsPlayed", 7)
+        ]);
+        let writer = enter(writer, &registry, 0, "CCSPlayerResource", 1, &[
+            ("m_iKills.001", 3), ("m_iDeaths.001", 1), ("m_iAssists.001", 2), ("m_iMVPs.001", 1), ("m_iPing.001", 40)
+        ]);
+        let writer = enter(writer, &registry, 0, "CAK47", 1, &[("m_iClip1", 10), ("m_hOwner", EntityHandle::INVALID.0)]);
+
+        let mut entities = EntityTable::new();
+        entities.read_packet_entities(&registry, &packet(true, 6, writer), |_| Ok(())).unwrap();
+        entities
+    }
+
+    #[test]
+    fn players_resolve_their_weapons_and_team() {
+        let entities = entities();
+        let players: Vec<_> = entities.players().collect();
+        assert_eq!(players.len(), 1);
+
+        let player = players[0];
+        assert_eq!(player.index(), 1);
+        assert_eq!((player.health(), player.armor(), player.money()), (Some(100), Some(50), Some(800)));
+        assert!(player.is_alive());
+        assert_eq!(player.team().and_then(|team| team.score()), Some(4));
+        assert_eq!(player.active_weapon().map(|weapon| weapon.entity.index), Some(2));
+        // The stale handle in the second weapon slot resolves to nothing.
+        assert_eq!(player.weapons().map(|weapon| weapon.entity.index).collect::<Vec<_>>(), vec![2]);
+        // Not sent by the server.
+        assert_eq!(player.position(), None);
+    }
+
+    #[test]
+    fn weapons_resolve_their_owner() {
+        let entities = entities();
+        let weapons: Vec<_> = entities.weapons().collect();
+        assert_eq!(weapons.iter().map(|weapon| weapon.entity.index).collect::<Vec<_>>(), vec![2, 6]);
+
+        let rifle = weapons[0];
+        assert_eq!((rifle.item_definition_index(), rifle.clip(), rifle.reserve()), (Some(7), Some(30), Some(90)));
+        assert_eq!(rifle.owner().map(|player| player.index()), Some(1));
+        assert!(weapons[1].owner().is_none());
+
+        // Views only wrap entities of their class.
+        assert!(Weapon::new(entities.get(1).unwrap(), &entities).is_none());
+        assert!(Player::new(entities.get(2).unwrap(), &entities).is_none());
+    }
+
+    #[test]
+    fn teams_list_their_players() {
+        let entities = entities();
+        let team = entities.teams().next().unwrap();
+        assert_eq!((team.team_num(), team.score()), (Some(3), Some(4)));
+        assert_eq!(team.players().map(|player| player.index()).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn game_rules_report_the_match_and_round_phase() {
+        let entities = entities();
+        let rules = entities.game_rules().unwrap();
+        assert_eq!(rules.game_phase(), Some(GamePhase::FirstHalf));
+        assert_eq!(rules.round_phase(), Some(RoundPhase::FreezeTime));
+        assert_eq!(rules.is_freeze_period(), Some(true));
+        assert_eq!(rules.is_bomb_planted(), Some(false));
+        assert_eq!(rules.is_warmup_period(), Some(false));
+        assert_eq!(rules.total_rounds_played(), Some(7));
+        assert_eq!(GamePhase::from(9), GamePhase::Unknown(9));
+    }
+
+    #[test]
+    fn player_resource_is_indexed_by_entity_index() {
+        let entities = entities();
+        let resource = entities.player_resource().unwrap();
+        let player = entities.players().next().unwrap();
+
+        assert_eq!(resource.kills(&player), Some(3));
+        assert_eq!(resource.deaths(&player), Some(1));
+        assert_eq!(resource.assists(&player), Some(2));
+        assert_eq!(resource.mvps(&player), Some(1));
+        assert_eq!(resource.ping(&player), Some(40));
+        assert_eq!(resource.players().count(), 1);
+    }
+}